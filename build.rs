@@ -0,0 +1,5 @@
+// Makes sure that the crate is rebuilt when a migration is added, as they are embedded
+// using `sqlx::migrate!`.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
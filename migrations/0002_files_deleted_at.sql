@@ -0,0 +1,6 @@
+ALTER TABLE tantivy.files
+ADD COLUMN deleted_at TIMESTAMPTZ;
+
+UPDATE tantivy.files
+SET deleted_at = NOW()
+WHERE deleted;
@@ -0,0 +1,3 @@
+ALTER TABLE tantivy.files
+ADD COLUMN length BIGINT,
+ADD COLUMN etag TEXT;
@@ -0,0 +1,12 @@
+CREATE TABLE tantivy.locks (
+    index UUID NOT NULL,
+    name TEXT NOT NULL,
+    holder_id UUID NOT NULL,
+    last_alive_at TIMESTAMPTZ NOT NULL,
+
+    FOREIGN KEY (index)
+    REFERENCES tantivy.directories(index)
+    ON DELETE CASCADE,
+
+    PRIMARY KEY (index, name)
+);
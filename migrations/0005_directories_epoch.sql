@@ -0,0 +1,2 @@
+ALTER TABLE tantivy.directories
+ADD COLUMN epoch BIGINT NOT NULL DEFAULT 0;
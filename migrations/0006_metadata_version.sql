@@ -0,0 +1,2 @@
+ALTER TABLE tantivy.metadata
+ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
@@ -0,0 +1,13 @@
+CREATE TABLE tantivy.revisions (
+    index UUID NOT NULL,
+    version BIGINT NOT NULL,
+    content BYTEA NOT NULL,
+    managed BYTEA,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    FOREIGN KEY (index)
+    REFERENCES tantivy.directories(index)
+    ON DELETE CASCADE,
+
+    PRIMARY KEY (index, version)
+);
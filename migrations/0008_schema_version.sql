@@ -0,0 +1,6 @@
+CREATE TABLE tantivy.schema_version (
+    version BIGINT NOT NULL
+);
+
+INSERT INTO tantivy.schema_version (version)
+VALUES (8);
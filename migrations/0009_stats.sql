@@ -0,0 +1,20 @@
+CREATE TABLE tantivy.stats (
+    index UUID NOT NULL,
+    version BIGINT NOT NULL,
+    opstamp BIGINT NOT NULL,
+    segments BIGINT NOT NULL,
+    docs BIGINT NOT NULL,
+    deleted_docs BIGINT NOT NULL,
+    payload TEXT,
+    schema_hash TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    FOREIGN KEY (index, version)
+    REFERENCES tantivy.revisions(index, version)
+    ON DELETE CASCADE,
+
+    PRIMARY KEY (index, version)
+);
+
+UPDATE tantivy.schema_version
+SET version = 9;
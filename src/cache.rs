@@ -1,23 +1,24 @@
 use std::{
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use derive_more::Deref;
-use opendal::Metadata;
+use derive_more::{Debug, Deref};
 use scc::hash_map::Entry;
 use tantivy::directory::{
     FileHandle,
     error::{OpenReadError, OpenWriteError},
 };
 
-use crate::utils::FastConcurrentMap;
-
-// TODO(MLB): clean up the cache when a file is closed/after some time?
+use crate::{CacheOptions, file::FileMetadata, utils::FastConcurrentMap};
 
 /// Caches opened files and their metadata, as well as the list of files which have
 /// been created and whether they have been flushed.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub(crate) struct Cache {
     /// Keeps track of the files which have been created, and whether they have been
     /// flushed, until the directory containing them is synced.
@@ -34,13 +35,14 @@ pub(crate) struct Cache {
 /// containing them is synced.
 #[derive(Debug, Default, Deref)]
 struct CreatedCache {
-    /// Contains, for each path created, whether the file has been flushed and closed.
+    /// Contains, for each path created, the metadata of the file once it has been
+    /// flushed and closed.
     #[deref]
-    cache: FastConcurrentMap<PathBuf, bool>,
+    cache: FastConcurrentMap<PathBuf, Option<FileMetadata>>,
 }
 
 /// An entry into the cache of created files, used to save that the file has been
-/// flushed.
+/// flushed, along with its metadata.
 pub(crate) struct CreatedEntry {
     path: PathBuf,
     cache: Arc<CreatedCache>,
@@ -52,27 +54,85 @@ pub(crate) struct CreatedEntry {
 }
 
 /// Caches the [`File`]s which have been opened.
-#[derive(Debug, Default, Deref)]
-pub(crate) struct FilesCache {
+#[derive(Debug, Deref)]
+struct FilesCache {
     #[deref]
-    cache: FastConcurrentMap<PathBuf, Arc<dyn FileHandle>>,
+    cache: BoundedCache<Arc<dyn FileHandle>>,
+
+    /// How long files are cached for.
+    ttl: Option<Duration>,
 }
 
-/// Caches the [`Metadata`]s which have been fetched.
-#[derive(Debug, Default, Deref)]
+/// Caches the [`FileMetadata`]s which have been fetched, as well as whether files do
+/// not exist.
+#[derive(Debug, Deref)]
 struct MetadataCache {
+    /// Contains, for each path, the metadata of the file or `None` if it does not
+    /// exist.
     #[deref]
-    cache: FastConcurrentMap<PathBuf, Arc<Metadata>>,
+    cache: BoundedCache<Option<Arc<FileMetadata>>>,
+
+    /// How long metadata is cached for.
+    ttl: Option<Duration>,
+
+    /// How long the non-existence of files is cached for, if it is.
+    negative_ttl: Option<Duration>,
+}
+
+/// A cache whose entries can expire, and which evicts its least recently used entries
+/// once it contains too many of them.
+#[derive(Debug)]
+#[debug("BoundedCache {{ capacity: {capacity} }}")]
+struct BoundedCache<V> {
+    cache: FastConcurrentMap<PathBuf, Cached<V>>,
+
+    /// The maximum number of entries in the cache.
+    capacity: usize,
+
+    /// The instant from which access times are measured.
+    origin: Instant,
+}
+
+/// A value stored in a [`BoundedCache`].
+struct Cached<V> {
+    value: V,
+
+    /// When the value expires, if it does.
+    expires_at: Option<Instant>,
+
+    /// When the value was last accessed, as the number of nanoseconds since
+    /// [`BoundedCache::origin`].
+    accessed_at: AtomicU64,
 }
 
 impl Cache {
+    /// Creates a new, empty, cache.
+    pub fn new(options: &CacheOptions) -> Self {
+        let files = FilesCache {
+            cache: BoundedCache::new(options.capacity),
+            ttl: options.ttl,
+        };
+
+        let metadata = MetadataCache {
+            cache: BoundedCache::new(options.capacity),
+            ttl: options.ttl,
+            negative_ttl: options.negative_ttl,
+        };
+
+        Self {
+            created: Arc::default(),
+            files: Arc::new(files),
+            metadata: Arc::new(metadata),
+        }
+    }
+
     /// Fetches the metadata for the given path from the cache, fetching it and
     /// populating the cache using the provided closure if it is not already cached.
     pub async fn metadata(
         &self,
         path: &Path,
-        fetch: impl AsyncFnOnce() -> Result<Metadata, OpenReadError>,
-    ) -> Result<Arc<Metadata>, OpenReadError> {
+        fetch: impl AsyncFnOnce() -> Result<FileMetadata, OpenReadError>,
+    ) -> Result<Arc<FileMetadata>, OpenReadError> {
         self.metadata.fetch(path, fetch).await
     }
 
@@ -83,31 +143,23 @@ impl Cache {
         path: &Path,
         open: impl AsyncFnOnce() -> Result<Arc<dyn FileHandle>, OpenReadError>,
     ) -> Result<Arc<dyn FileHandle>, OpenReadError> {
-        // fast path: try to get the file handle from the cache – this does not lock other
-        //            readers.
-        if let Some(file) = self.files.read_sync(path, |_, file| Arc::clone(file)) {
-            return Ok(file);
-        }
-
-        // slow path: get the entry and insert into it if it is still missing.
-        let entry = self.files.entry_sync(path.to_path_buf());
-        let entry = match entry {
-            Entry::Occupied(entry) => entry,
-            Entry::Vacant(entry) => {
-                // TODO(MLB): avoid keeping the lock while opening the file?
-                let file = open().await?;
-                entry.insert_entry(file)
-            }
-        };
-
-        Ok(Arc::clone(entry.get()))
+        let open = async || open().await.map(|file| (file, self.files.ttl));
+        self.files.fetch(path, open).await
     }
 
     /// Marks the file at the given path as having been created, returning a
     /// [`CreatedEntry`] for it so that it can later be marked as having been flushed.
-    pub async fn created(&self, filepath: PathBuf) -> Result<CreatedEntry, OpenWriteError> {
-        let filepath = filepath.to_path_buf();
-        let result = self.created.insert_async(filepath.clone(), false).await;
+    ///
+    /// `path` is the path of the file in the object storage, which is invalidated in
+    /// case the cache remembers that it did not exist.
+    pub async fn created(
+        &self,
+        filepath: PathBuf,
+        path: &Path,
+    ) -> Result<CreatedEntry, OpenWriteError> {
+        self.invalidate(path);
+
+        let result = self.created.insert_async(filepath.clone(), None).await;
         match result {
             Ok(_) => Ok(CreatedEntry {
                 path: filepath,
@@ -118,14 +170,74 @@ impl Cache {
             Err(_) => Err(OpenWriteError::FileAlreadyExists(filepath)),
         }
     }
+
+    /// Returns the paths of the files which have been created and flushed since the
+    /// last time [`synced()`][1] was called, along with their metadata.
+    ///
+    /// [1]: Self::synced
+    pub async fn flushed(&self) -> Vec<(PathBuf, FileMetadata)> {
+        let mut files = Vec::new();
+        self.created
+            .iter_async(|path, metadata| {
+                if let Some(metadata) = metadata {
+                    files.push((path.clone(), metadata.clone()));
+                }
+
+                true
+            })
+            .await;
+
+        files
+    }
+
+    /// Returns the paths of all the files which have been created but not synced yet,
+    /// whether they have been flushed or not.
+    pub async fn unsynced(&self) -> Vec<PathBuf> {
+        let mut paths = Vec::new();
+        self.created
+            .iter_async(|path, _| {
+                paths.push(path.clone());
+                true
+            })
+            .await;
+
+        paths
+    }
+
+    /// Removes the file which has been deleted from the cache.
+    ///
+    /// `filepath` is the path of the file as seen by `tantivy`, while `path` is its
+    /// path in the object storage.
+    pub async fn deleted(&self, filepath: &Path, path: &Path) {
+        self.created.remove_async(filepath).await;
+        self.invalidate(path);
+    }
+
+    /// Removes the file at the given path in the object storage from the caches of
+    /// opened files and of metadata.
+    pub fn invalidate(&self, path: &Path) {
+        self.files.remove(path);
+        self.metadata.remove(path);
+    }
+
+    /// Removes the given paths from the cache of created files, once the directory
+    /// containing them has been synced.
+    pub async fn synced(&self, paths: impl IntoIterator<Item = &PathBuf>) {
+        for path in paths {
+            self.created.remove_async(path).await;
+        }
+    }
 }
 
 impl CreatedEntry {
-    /// Marks the file as having been flushed and closed.
-    pub fn done(&mut self) {
+    /// Marks the file as having been flushed and closed, after `length` bytes were
+    /// written to it.
+    pub fn done(&mut self, length: u64) {
         if !self.done {
             self.done = true;
-            self.cache.update_sync(&self.path, |_, done| *done = true);
+            self.cache.update_sync(&self.path, |_, metadata| {
+                *metadata = Some(FileMetadata { length, etag: None });
+            });
         }
     }
 }
@@ -136,34 +248,152 @@ impl MetadataCache {
     async fn fetch(
         &self,
         path: &Path,
-        fetch: impl AsyncFnOnce() -> Result<Metadata, OpenReadError>,
-    ) -> Result<Arc<Metadata>, OpenReadError> {
-        // fast path: try to read the metadata from the cache – this does not lock other readers.
-        if let Some(metadata) = self
-            .read_async(path, |_, metadata| Arc::clone(metadata))
-            .await
-        {
-            return Ok(metadata);
+        fetch: impl AsyncFnOnce() -> Result<FileMetadata, OpenReadError>,
+    ) -> Result<Arc<FileMetadata>, OpenReadError> {
+        let fetch = async || match fetch().await {
+            Ok(metadata) => Ok((Some(Arc::new(metadata)), self.ttl)),
+            Err(OpenReadError::FileDoesNotExist(path)) => match self.negative_ttl {
+                Some(ttl) => Ok((None, Some(ttl))),
+                None => Err(OpenReadError::FileDoesNotExist(path)),
+            },
+
+            Err(error) => Err(error),
+        };
+
+        self.cache
+            .fetch(path, fetch)
+            .await?
+            .ok_or_else(|| OpenReadError::FileDoesNotExist(path.to_path_buf()))
+    }
+}
+
+impl<V: Clone> BoundedCache<V> {
+    /// Creates a new, empty, cache which can contain at most `capacity` entries.
+    fn new(capacity: usize) -> Self {
+        Self {
+            cache: FastConcurrentMap::default(),
+            capacity,
+            origin: Instant::now(),
+        }
+    }
+
+    /// Fetches the value for the given path from the cache, populating it using the
+    /// provided async closure if it is not already cached or if it has expired.
+    ///
+    /// The closure returns the value along with how long it should be cached for.
+    async fn fetch<E>(
+        &self,
+        path: &Path,
+        fetch: impl AsyncFnOnce() -> Result<(V, Option<Duration>), E>,
+    ) -> Result<V, E> {
+        let now = Instant::now();
+
+        // fast path: try to read the value from the cache – this does not lock other readers.
+        let cached = self.cache.read_sync(path, |_, cached| {
+            (!cached.expired(now)).then(|| cached.access(self.since_origin(now)))
+        });
+
+        if let Some(Some(value)) = cached {
+            return Ok(value);
         }
 
-        // slow path: get the entry for the file and insert if it is still missing
-        let entry = self.entry_sync(path.to_path_buf());
-        let entry = match entry {
-            Entry::Occupied(entry) => entry,
-            Entry::Vacant(entry) => {
-                // TODO(MLB): cache whether the file exists or not?
-                // TODO(MLB): avoid keeping the lock while fetching the metadata?
-                let metadata = fetch().await.map(Arc::new)?;
-                entry.insert_entry(metadata)
+        // slow path: get the entry and insert into it if it is still missing or expired.
+        let value = {
+            let entry = self.cache.entry_sync(path.to_path_buf());
+            match entry {
+                Entry::Occupied(entry) if !entry.get().expired(now) => {
+                    entry.get().access(self.since_origin(now))
+                }
+
+                entry => {
+                    // TODO(MLB): avoid keeping the lock while fetching the value?
+                    let (value, ttl) = fetch().await?;
+                    let cached = Cached {
+                        value: value.clone(),
+                        expires_at: ttl.map(|ttl| now + ttl),
+                        accessed_at: AtomicU64::new(self.since_origin(now)),
+                    };
+
+                    match entry {
+                        Entry::Occupied(mut entry) => *entry.get_mut() = cached,
+                        Entry::Vacant(entry) => {
+                            entry.insert_entry(cached);
+                        }
+                    }
+
+                    value
+                }
             }
         };
 
-        Ok(Arc::clone(entry.get()))
+        self.evict(now);
+
+        Ok(value)
+    }
+
+    /// Removes the value for the given path from the cache.
+    fn remove(&self, path: &Path) {
+        self.cache.remove_sync(path);
+    }
+
+    /// Evicts the expired entries from the cache if it contains more entries than its
+    /// capacity, and then the least recently used ones until it is back to 90% of its
+    /// capacity, so that this does not happen every time a value is inserted.
+    fn evict(&self, now: Instant) {
+        if self.cache.len() <= self.capacity {
+            return;
+        }
+
+        self.cache.retain_sync(|_, cached| !cached.expired(now));
+
+        let len = self.cache.len();
+        if len <= self.capacity {
+            return;
+        }
+
+        let mut accesses = Vec::with_capacity(len);
+        self.cache.iter_sync(|path, cached| {
+            let accessed_at = cached.accessed_at.load(Ordering::Relaxed);
+            accesses.push((accessed_at, path.clone()));
+            true
+        });
+
+        accesses.sort_unstable_by_key(|(accessed_at, _)| *accessed_at);
+
+        let target = self.capacity - self.capacity / 10;
+        let evicted = len.saturating_sub(target);
+        for (_, path) in accesses.into_iter().take(evicted) {
+            self.cache.remove_sync(&path);
+        }
+    }
+
+    /// Returns the number of nanoseconds between [`origin`][1] and `now`.
+    ///
+    /// [1]: Self::origin
+    fn since_origin(&self, now: Instant) -> u64 {
+        now.duration_since(self.origin).as_nanos() as u64
+    }
+}
+
+impl<V: Clone> Cached<V> {
+    /// Returns whether the value has expired.
+    fn expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Returns the value, saving that it has been accessed at `accessed_at`.
+    fn access(&self, accessed_at: u64) -> V {
+        self.accessed_at.store(accessed_at, Ordering::Relaxed);
+        self.value.clone()
     }
 }
 
 impl Drop for CreatedEntry {
     fn drop(&mut self) {
-        self.cache.remove_sync(&self.path);
+        // Files which have been flushed are kept until the directory is synced, so that
+        // they can be registered.
+        if !self.done {
+            self.cache.remove_sync(&self.path);
+        }
     }
 }
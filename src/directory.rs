@@ -1,48 +1,77 @@
+mod commit;
+mod gc;
+mod history;
+mod orphans;
+mod pinned;
+mod stats;
+
 use std::{
     io,
     path::{Path, PathBuf},
-    sync::{Arc, LazyLock},
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicI64, Ordering},
+    },
 };
 
 use derive_more::Debug;
-use eyre::Result;
-use opendal::Metadata;
+use eyre::{Result, bail};
 use sqlx::PgPool;
 use tantivy::{
     Directory, TantivyError,
     directory::{
-        DirectoryLock, FileHandle, Lock, WatchCallback, WatchHandle, WritePtr,
+        DirectoryLock, FileHandle, INDEX_WRITER_LOCK, Lock, WatchCallback, WatchHandle, WritePtr,
         error::{DeleteError, LockError, OpenReadError, OpenWriteError},
     },
 };
 use tokio::runtime::Handle;
 use uuid::Uuid;
 
+#[cfg(feature = "notify")]
+use crate::watch::Listener;
 use crate::{
+    FileMetadata, Locking, MetadataBackend, Options, PostgresBackend, Watching,
     cache::Cache,
     file::File,
-    metadata::MetadataStore,
+    lock::{AdvisoryLock, LeaseLock},
+    metadata::{Expected, MetadataStore, RegisteredFile, WriteError},
     operator::Operator,
-    utils::{PathExt, WrapIoErrorExt},
+    utils::{FastConcurrentMap, PathExt, WrapIoErrorExt},
+    watch::Poller,
     writer::Writer,
 };
 
+use self::pinned::Pinned;
+pub use self::{commit::Commit, gc::GarbageCollection, orphans::Orphan};
+
 // TODO(MLB): replace with `const`s once the `const` version of `Path::new` is stabilized
-static META_JSON: LazyLock<&'static Path> = LazyLock::new(|| Path::new("meta.json"));
-static MANAGED_JSON: LazyLock<&'static Path> = LazyLock::new(|| Path::new(".managed.json"));
+pub(crate) static META_JSON: LazyLock<&'static Path> = LazyLock::new(|| Path::new("meta.json"));
+pub(crate) static MANAGED_JSON: LazyLock<&'static Path> =
+    LazyLock::new(|| Path::new(".managed.json"));
 
 /// A [`Directory`] implementation that reads and writes files to a remote object
-/// storage using [`opendal`], with metadata stored in PostgreSQL.
+/// storage using [`opendal`], with metadata stored in PostgreSQL or in another
+/// [`MetadataBackend`].
+///
+/// By default, this does not support watching for updates to the metadata files.
+/// Instead, the readers using this directory should be created using
+/// [`ReloadPolicy::Manual`][1] and reloaded manually. Watching can be enabled using
+/// [`Options::watching`].
 ///
-/// This does not support watching for updates to the metadata files. Instead, the
-/// readers using this directory should be created using [`ReloadPolicy::Manual`][1]
-/// and reloaded manually.
+/// Files deleted by `tantivy` are only marked as deleted in the metadata, and are only
+/// removed from the remote object storage once [`collect_garbage()`][2] is called.
 ///
-/// This also does not implement any locking logic. It is up to the user of this
-/// directory to make sure that there can only be one index writer using it at any
-/// given time.
+/// By default, this also does not implement any locking logic, and it is up to the
+/// user of this directory to make sure that there can only be one index writer using
+/// it at any given time. Locking can be enabled using [`Options::locking`].
+///
+/// Regardless, metadata files which have been read using `atomic_read()` are only
+/// written if nobody else has written them since, and writing them fails with
+/// [`WriteError::Conflict`][3] otherwise.
 ///
 /// [1]: tantivy::ReloadPolicy::Manual
+/// [2]: Self::collect_garbage
+/// [3]: crate::WriteError::Conflict
 #[derive(Clone, Debug)]
 #[debug("RemoteDirectory {{ index: {index} }}")]
 pub struct RemoteDirectory {
@@ -65,6 +94,38 @@ pub struct RemoteDirectory {
     /// [1]: Directory::atomic_read()
     /// [2]: Directory::atomic_write()
     metadata: MetadataStore,
+
+    /// How locks are acquired.
+    locking: Locking,
+
+    /// How changes to `meta.json` are watched for.
+    watching: Watching,
+
+    /// The fencing epoch obtained when the index writer lock was last acquired, or `0`
+    /// if it has not been acquired.
+    ///
+    /// This is shared with all the clones of the directory, as `tantivy` might write
+    /// the metadata using a different clone than the one used to acquire the lock.
+    epoch: Arc<AtomicI64>,
+
+    /// The revision the directory is pinned at, if it has been opened using
+    /// [`open_at()`][1], in which case it is read-only.
+    ///
+    /// [1]: Self::open_at
+    pinned: Option<Arc<Pinned>>,
+
+    /// Contains, for each metadata file which has been read or written, its version at
+    /// that time or `None` if it did not exist, so that it is only written if it has
+    /// not been written by someone else since.
+    ///
+    /// This is shared with all the clones of the directory.
+    versions: Arc<FastConcurrentMap<PathBuf, Option<i64>>>,
+
+    /// Contains the files which have been synced but not registered yet, along with
+    /// their metadata, which are registered along with the next write of `meta.json`.
+    ///
+    /// This is shared with all the clones of the directory.
+    unregistered: Arc<FastConcurrentMap<String, FileMetadata>>,
 }
 
 impl RemoteDirectory {
@@ -76,43 +137,190 @@ impl RemoteDirectory {
     ///
     /// This will panic if called from outside of the context of a `tokio` runtime.
     pub async fn open(index: Uuid, operator: opendal::Operator, pool: PgPool) -> Result<Self> {
-        let metadata = MetadataStore::open(index, pool).await?;
+        Self::open_with_options(index, operator, pool, Options::default()).await
+    }
+
+    /// Creates a new directory to read/write from/to the given index, configured using
+    /// the given options.
+    ///
+    /// If the index does not exist, it creates it.
+    ///
+    /// ## Panics
+    ///
+    /// This will panic if called from outside of the context of a `tokio` runtime.
+    pub async fn open_with_options(
+        index: Uuid,
+        operator: opendal::Operator,
+        pool: PgPool,
+        options: Options,
+    ) -> Result<Self> {
+        let backend = PostgresBackend::new(pool).with_history(options.history.clone());
+        Self::open_with_backend(index, operator, backend, options).await
+    }
+
+    /// Creates a new directory to read/write from/to the given index, storing its
+    /// metadata using the given backend and configured using the given options.
+    ///
+    /// If the index does not exist, it creates it.
+    ///
+    /// Locking and watching require the metadata to be stored in PostgreSQL, and this
+    /// fails if they are enabled with another backend, or if the options are invalid.
+    /// [`Options::history`] is only used by [`open_with_options()`][1], and backends
+    /// are otherwise configured on their own.
+    ///
+    /// ## Panics
+    ///
+    /// This will panic if called from outside of the context of a `tokio` runtime.
+    ///
+    /// [1]: Self::open_with_options
+    pub async fn open_with_backend(
+        index: Uuid,
+        operator: opendal::Operator,
+        backend: impl MetadataBackend,
+        options: Options,
+    ) -> Result<Self> {
+        options.validate()?;
+
+        if backend.postgres().is_none() {
+            if !matches!(options.locking, Locking::Disabled) {
+                bail!("locking requires the metadata to be stored in PostgreSQL");
+            } else if !matches!(options.watching, Watching::Disabled) {
+                bail!("watching requires the metadata to be stored in PostgreSQL");
+            }
+        }
+
+        let metadata = MetadataStore::open(index, Arc::new(backend)).await?;
 
         Ok(Self {
             index,
             rt: Handle::current(),
-            cache: Cache::default(),
+            cache: Cache::new(&options.cache),
             operator: Operator::from(operator),
             metadata,
+            locking: options.locking,
+            watching: options.watching,
+            epoch: Arc::default(),
+            pinned: None,
+            versions: Arc::default(),
+            unregistered: Arc::default(),
         })
     }
 
+    /// Returns the backend storing the metadata in PostgreSQL.
+    ///
+    /// ## Panics
+    ///
+    /// This will panic if the metadata is not stored in PostgreSQL, which is checked
+    /// when the directory is opened with locking or watching enabled.
+    fn postgres(&self) -> &PostgresBackend {
+        self.metadata
+            .postgres()
+            .expect("locking and watching require PostgreSQL")
+    }
+
     /// Returns the path that should be used for the file at `path` for the index.
     ///
     /// This should not be used for metadata files.
     fn path(&self, path: impl AsRef<Path>) -> PathBuf {
-        let base = format!("idx-{}", self.index);
-        let mut base = PathBuf::from(base);
+        let mut base = PathBuf::from(self.prefix());
         base.push(path);
         base
     }
 
-    /// Fetches the metadata for the given path.
-    async fn metadata(&self, path: &Path) -> Result<Arc<Metadata>, OpenReadError> {
-        // TODO(MLB): check whether the file exists + has not been deleted in PSQL
+    /// Returns the prefix under which the files of the index are stored.
+    fn prefix(&self) -> String {
+        format!("idx-{}/", self.index)
+    }
+
+    /// Returns the fencing epoch obtained when the index writer lock was last acquired,
+    /// if it has been.
+    fn epoch(&self) -> Option<i64> {
+        match self.epoch.load(Ordering::Acquire) {
+            0 => None,
+            epoch => Some(epoch),
+        }
+    }
+
+    /// Writes `meta.json`, registering the files which have been synced since the last
+    /// commit in the same transaction if the backend supports it, so that they are
+    /// only registered if the commit lands.
+    async fn commit(
+        &self,
+        path: &str,
+        content: &[u8],
+        expected: Expected,
+    ) -> Result<i64, WriteError> {
+        let mut files = Vec::new();
+        self.unregistered
+            .iter_async(|path, metadata| {
+                files.push((path.clone(), metadata.clone()));
+                true
+            })
+            .await;
+
+        let commit = self
+            .metadata
+            .commit(&files, path, content, self.epoch(), expected);
+        let version = commit.await?;
+
+        // Files which were synced again in the meantime are registered by the next
+        // commit.
+        for (path, metadata) in &files {
+            self.unregistered
+                .remove_if_async(path, |current| current == metadata)
+                .await;
+        }
 
-        let fetch = async || self.operator.metadata(path).await;
-        self.cache.metadata(path, fetch).await
+        Ok(version)
+    }
+
+    /// Fetches the metadata for the file at the given path.
+    ///
+    /// The metadata is read from the metadata store, falling back to the object storage
+    /// for files which have not been registered yet.
+    async fn metadata(&self, filepath: &Path) -> Result<Arc<FileMetadata>, OpenReadError> {
+        let path = self.path(filepath);
+
+        let fetch = async || {
+            let name = filepath.try_to_str::<OpenReadError>()?;
+            let file = self
+                .metadata
+                .file(name)
+                .await
+                .map_err(OpenReadError::wrapper(filepath))?;
+
+            match file {
+                // Pinned directories can read files which have been deleted since.
+                Some(RegisteredFile { deleted: true, .. }) if self.pinned.is_none() => {
+                    Err(OpenReadError::FileDoesNotExist(filepath.to_path_buf()))
+                }
+
+                Some(RegisteredFile {
+                    length: Some(length),
+                    etag,
+                    ..
+                }) => Ok(FileMetadata {
+                    length: length as u64,
+                    etag,
+                }),
+
+                // Files are only registered once they have been committed, and files
+                // registered before their length was stored do not have it.
+                _ => self.operator.metadata(&path).await,
+            }
+        };
+
+        self.cache.metadata(&path, fetch).await
     }
 }
 
 impl Directory for RemoteDirectory {
-    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
-        let path = self.path(path);
+    fn get_file_handle(&self, filepath: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let path = self.path(filepath);
 
         self.rt.block_on(async {
             let open = async || {
-                let metadata = self.metadata(&path).await?;
+                let metadata = self.metadata(filepath).await?;
 
                 let path = path.try_to_str::<OpenReadError>()?;
                 let file = File::open(path, metadata, self.rt.clone(), self.operator.clone());
@@ -124,17 +332,39 @@ impl Directory for RemoteDirectory {
         })
     }
 
-    fn delete(&self, _filepath: &Path) -> Result<(), DeleteError> {
-        // TODO(MLB): mark the file as deleted in PSQL
-        // TODO(MLB): add a TLL to the files in S3
+    fn delete(&self, filepath: &Path) -> Result<(), DeleteError> {
+        if self.pinned.is_some() {
+            return Err(DeleteError::wrap(pinned::read_only(), filepath));
+        }
+
+        let path = filepath.try_to_str::<DeleteError>()?;
 
-        Ok(())
+        self.rt.block_on(async {
+            // The file is only marked as deleted – removing it from the object storage
+            // is left to a later step, as readers might still be using it. It must also
+            // not be registered by the next commit, if it was not committed yet.
+            self.unregistered.remove_async(path).await;
+            self.metadata
+                .delete(path)
+                .await
+                .map_err(DeleteError::wrapper(filepath))?;
+
+            self.cache.deleted(filepath, &self.path(filepath)).await;
+
+            Ok(())
+        })
     }
 
     fn exists(&self, filepath: &Path) -> Result<bool, OpenReadError> {
         // For files which are written using `atomic_write()`, we have to look inside
-        // PostgreSQL to know whether they exist.
-        if filepath == *META_JSON || filepath == *MANAGED_JSON {
+        // the metadata store to know whether they exist.
+        if let Some(pinned) = &self.pinned {
+            if filepath == *META_JSON {
+                return Ok(true);
+            } else if filepath == *MANAGED_JSON {
+                return Ok(pinned.revision.managed.is_some());
+            }
+        } else if filepath == *META_JSON || filepath == *MANAGED_JSON {
             let path = filepath.try_to_str::<OpenReadError>()?;
             return self
                 .rt
@@ -142,8 +372,7 @@ impl Directory for RemoteDirectory {
                 .map_err(OpenReadError::wrapper(filepath));
         }
 
-        let filepath = self.path(filepath);
-        let result = self.rt.block_on(self.metadata(&filepath));
+        let result = self.rt.block_on(self.metadata(filepath));
         match result {
             Ok(_) => Ok(true),
             Err(error) => {
@@ -157,6 +386,13 @@ impl Directory for RemoteDirectory {
     }
 
     fn open_write(&self, filepath: &Path) -> Result<WritePtr, OpenWriteError> {
+        if self.pinned.is_some() {
+            return Err(OpenWriteError::wrap(pinned::read_only(), filepath));
+        }
+
+        // The cache of created files is keyed by the paths as seen by `tantivy`, so that
+        // they can be registered as-is when the directory is synced.
+        let created = filepath.to_path_buf();
         let filepath = self.path(filepath);
         let path = filepath.try_to_str::<OpenWriteError>()?;
 
@@ -174,8 +410,7 @@ impl Directory for RemoteDirectory {
                 }
             };
 
-            let filepath = filepath.to_path_buf();
-            let entry = self.cache.created(filepath).await?;
+            let entry = self.cache.created(created, &filepath).await?;
 
             Ok(Writer::new(entry, writer, self.rt.clone()))
         })?;
@@ -187,37 +422,160 @@ impl Directory for RemoteDirectory {
     }
 
     fn atomic_read(&self, filepath: &Path) -> Result<Vec<u8>, OpenReadError> {
+        if let Some(pinned) = &self.pinned {
+            let content = if filepath == *META_JSON {
+                Some(&pinned.revision.content)
+            } else if filepath == *MANAGED_JSON {
+                pinned.revision.managed.as_ref()
+            } else {
+                None
+            };
+
+            return content
+                .cloned()
+                .ok_or_else(|| OpenReadError::FileDoesNotExist(filepath.into()));
+        }
+
         let path = filepath.try_to_str::<OpenReadError>()?;
+        let read = self
+            .rt
+            .block_on(self.metadata.read_with_version(path))
+            .map_err(OpenReadError::wrapper(filepath))?;
+
+        // The version is remembered so that writing the file fails if someone else
+        // wrote it in the meantime.
+        let version = read.as_ref().map(|(_, version)| *version);
+        self.versions.upsert_sync(filepath.to_path_buf(), version);
 
-        self.rt
-            .block_on(self.metadata.read(path))
-            .map_err(OpenReadError::wrapper(filepath))?
+        read.map(|(content, _)| content)
             .ok_or_else(|| OpenReadError::FileDoesNotExist(filepath.into()))
     }
 
     fn atomic_write(&self, filepath: &Path, data: &[u8]) -> io::Result<()> {
+        if self.pinned.is_some() {
+            return Err(pinned::read_only());
+        }
+
         let path = filepath.try_to_str::<io::Error>()?;
+        let expected = match self.versions.read_sync(filepath, |_, version| *version) {
+            None => Expected::Any,
+            Some(None) => Expected::Missing,
+            Some(Some(version)) => Expected::Version(version),
+        };
+
+        let version = if filepath == *META_JSON {
+            self.rt.block_on(self.commit(path, data, expected))
+        } else {
+            let write = self.metadata.write(path, data, self.epoch(), expected);
+            self.rt.block_on(write)
+        };
+
+        let version = version.map_err(io::Error::wrapper(filepath))?;
+
+        self.versions
+            .upsert_sync(filepath.to_path_buf(), Some(version));
 
-        self.rt
-            .block_on(self.metadata.write(path, data))
-            .map_err(io::Error::wrapper(filepath))
+        Ok(())
     }
 
     fn sync_directory(&self) -> io::Result<()> {
-        // TODO(MLB): add the files which have been flushed to PSQL
-        // TODO(MLB): remove from the cache
+        self.rt.block_on(async {
+            let flushed = self.cache.flushed().await;
+            if flushed.is_empty() {
+                return Ok(());
+            }
 
-        Ok(())
+            // The files are only registered along with the next write of `meta.json`,
+            // using the metadata recorded when they were closed.
+            for (filepath, metadata) in &flushed {
+                let path = filepath.try_to_str::<io::Error>()?;
+                self.unregistered
+                    .upsert_async(path.to_owned(), metadata.clone())
+                    .await;
+            }
+
+            let synced = flushed.iter().map(|(filepath, _)| filepath);
+            self.cache.synced(synced).await;
+
+            Ok(())
+        })
     }
 
-    fn watch(&self, _cb: WatchCallback) -> tantivy::Result<WatchHandle> {
-        let error =
-            "watching is not supported by this directory, use `ReloadingPolicy::Manual`".into();
+    fn watch(&self, callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        // The metadata of pinned directories never changes.
+        if self.pinned.is_some() {
+            return Ok(WatchHandle::empty());
+        }
+
+        match self.watching {
+            Watching::Disabled => {
+                let error = "watching is disabled for this directory, use \
+                             `ReloadingPolicy::Manual` or enable it using `Options::watching`"
+                    .into();
+
+                Err(TantivyError::InternalError(error))
+            }
+
+            #[cfg(feature = "notify")]
+            Watching::Notify => {
+                let listener = self
+                    .rt
+                    .block_on(Listener::get(self.postgres().pool(), &self.rt))
+                    .map_err(|error| TantivyError::InternalError(error.to_string()))?;
 
-        Err(TantivyError::InternalError(error))
+                Ok(listener.subscribe(self.index, callback))
+            }
+
+            Watching::Poll { interval } => {
+                let subscribe =
+                    Poller::subscribe(self.postgres(), interval, &self.rt, self.index, callback);
+
+                self.rt
+                    .block_on(subscribe)
+                    .map_err(|error| TantivyError::InternalError(error.to_string()))
+            }
+        }
     }
 
-    fn acquire_lock(&self, _lock: &Lock) -> Result<DirectoryLock, LockError> {
-        Ok(DirectoryLock::from(Box::new(())))
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        if self.pinned.is_some() {
+            if lock.filepath == INDEX_WRITER_LOCK.filepath {
+                return Err(LockError::wrap_io_error(pinned::read_only()));
+            }
+
+            return Ok(DirectoryLock::from(Box::new(())));
+        }
+
+        let acquired = match self.locking {
+            Locking::Disabled => return Ok(DirectoryLock::from(Box::new(()))),
+            Locking::Advisory => {
+                let pool = self.postgres().pool();
+                let acquire = AdvisoryLock::acquire(self.index, lock, pool, self.rt.clone());
+                let lock = self.rt.block_on(acquire)?;
+
+                DirectoryLock::from(Box::new(lock))
+            }
+
+            Locking::Lease { ttl } => {
+                let acquire =
+                    LeaseLock::acquire(self.index, lock, ttl, self.postgres(), self.rt.clone());
+                let lock = self.rt.block_on(acquire)?;
+
+                DirectoryLock::from(Box::new(lock))
+            }
+        };
+
+        // Writers which acquired the index writer lock before this one can no longer
+        // write the metadata.
+        if lock.filepath == INDEX_WRITER_LOCK.filepath {
+            let epoch = self
+                .rt
+                .block_on(self.metadata.fence())
+                .map_err(|error| LockError::wrap_io_error(io::Error::other(error)))?;
+
+            self.epoch.store(epoch, Ordering::Release);
+        }
+
+        Ok(acquired)
     }
 }
@@ -0,0 +1,100 @@
+use std::{io, time::Duration};
+
+use eyre::{Context, OptionExt, Result};
+use serde_json::Value;
+use tantivy::Opstamp;
+use tokio::time;
+
+use super::{META_JSON, RemoteDirectory};
+use crate::utils::PathExt;
+
+/// How long to wait before checking whether a commit has been reached for the first
+/// time, which doubles after every check.
+const MIN_DELAY: Duration = Duration::from_millis(10);
+
+/// The maximum amount of time to wait between checks.
+const MAX_DELAY: Duration = Duration::from_millis(500);
+
+/// Identifies a commit of an index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Commit {
+    /// The commit with the given opstamp, as returned by `IndexWriter::commit()`.
+    Opstamp(Opstamp),
+
+    /// The commit which wrote the given version of `meta.json`, as returned by
+    /// [`RemoteDirectory::version()`].
+    Version(i64),
+}
+
+impl RemoteDirectory {
+    /// Returns the version of the stored `meta.json`, which is incremented every time
+    /// a commit is made, or `None` if no commit has been made yet.
+    pub async fn version(&self) -> Result<Option<i64>> {
+        let path = META_JSON.try_to_str::<io::Error>()?;
+
+        self.metadata
+            .version(path)
+            .await
+            .wrap_err("failed to read version")
+    }
+
+    /// Waits until the stored `meta.json` has reached the given commit, so that
+    /// readers reloaded afterwards see it.
+    ///
+    /// Returns `false` if it was not reached before `timeout` elapsed.
+    pub async fn wait_for_commit(&self, commit: Commit, timeout: Duration) -> Result<bool> {
+        let wait = async {
+            let mut delay = MIN_DELAY;
+            while !self.reached(commit).await? {
+                time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_DELAY);
+            }
+
+            Ok(())
+        };
+
+        match time::timeout(timeout, wait).await {
+            Ok(result) => result.map(|()| true),
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Returns whether the stored `meta.json` has reached the given commit.
+    async fn reached(&self, commit: Commit) -> Result<bool> {
+        let path = META_JSON.try_to_str::<io::Error>()?;
+
+        match commit {
+            Commit::Opstamp(opstamp) => {
+                let meta = self
+                    .metadata
+                    .read(path)
+                    .await
+                    .wrap_err("failed to read meta.json")?;
+
+                let Some(meta) = meta else {
+                    return Ok(false);
+                };
+
+                let meta =
+                    serde_json::from_slice::<Value>(&meta).wrap_err("failed to parse meta.json")?;
+
+                let current = meta
+                    .get("opstamp")
+                    .and_then(Value::as_u64)
+                    .ok_or_eyre("meta.json does not contain an opstamp")?;
+
+                Ok(current >= opstamp)
+            }
+
+            Commit::Version(version) => {
+                let current = self
+                    .metadata
+                    .version(path)
+                    .await
+                    .wrap_err("failed to read version")?;
+
+                Ok(current.is_some_and(|current| current >= version))
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use eyre::{Context, Result};
+use opendal::ErrorKind;
+use tokio::{task::JoinHandle, time};
+
+use super::RemoteDirectory;
+
+/// The maximum number of files which are removed at once.
+const BATCH_SIZE: i64 = 1000;
+
+/// Reports what has been reclaimed by a garbage collection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GarbageCollection {
+    /// The number of objects which have been removed from the object storage.
+    pub objects: u64,
+
+    /// The total size of the objects which have been removed, in bytes.
+    pub bytes: u64,
+}
+
+impl RemoteDirectory {
+    /// Removes the files which have been marked as deleted for longer than `grace`
+    /// from the object storage, and then forgets about them.
+    ///
+    /// The grace period should be longer than the time it takes for readers to reload,
+    /// so that readers still using the deleted files can keep doing so.
+    ///
+    /// The reclaimed bytes are counted using the lengths stored along with the files,
+    /// so objects which were already removed by a previous collection which failed
+    /// before forgetting about them might be counted again.
+    pub async fn collect_garbage(&self, grace: Duration) -> Result<GarbageCollection> {
+        let mut collection = GarbageCollection::default();
+
+        loop {
+            let deleted = self
+                .metadata
+                .deleted(grace, BATCH_SIZE)
+                .await
+                .wrap_err("failed to list deleted files")?;
+
+            if deleted.is_empty() {
+                return Ok(collection);
+            }
+
+            let mut paths = Vec::with_capacity(deleted.len());
+            let mut objects = Vec::with_capacity(deleted.len());
+            for file in deleted {
+                let path = self.path(&file.path);
+                let path = path
+                    .to_str()
+                    .expect("paths built from strings are valid UTF-8")
+                    .to_owned();
+
+                paths.push(file.path);
+
+                // Only files whose length was not stored have to be stat'ed.
+                let length = match file.length {
+                    Some(length) => length as u64,
+                    None => match self.operator.stat(&path).await {
+                        Ok(metadata) => metadata.content_length(),
+
+                        // The object might have been removed by a previous collection
+                        // which failed before forgetting about it.
+                        Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                        Err(error) => return Err(error).wrap_err("failed to stat deleted file"),
+                    },
+                };
+
+                collection.objects += 1;
+                collection.bytes += length;
+                objects.push(path);
+            }
+
+            self.operator
+                .delete_iter(objects)
+                .await
+                .wrap_err("failed to remove deleted files")?;
+
+            self.metadata
+                .purge(&paths)
+                .await
+                .wrap_err("failed to purge deleted files")?;
+        }
+    }
+
+    /// Spawns a task calling [`collect_garbage()`][1] every `interval`.
+    ///
+    /// The task runs until it is aborted. Garbage collections which fail are logged,
+    /// and retried at the next interval.
+    ///
+    /// [1]: Self::collect_garbage
+    pub fn spawn_garbage_collector(&self, grace: Duration, interval: Duration) -> JoinHandle<()> {
+        let directory = self.clone();
+
+        self.rt.spawn(async move {
+            let mut interval = time::interval(interval);
+            loop {
+                interval.tick().await;
+                if let Err(error) = directory.collect_garbage(grace).await {
+                    tracing::warn!(
+                        index = %directory.index,
+                        error = format!("{error:#}"),
+                        "failed to collect garbage",
+                    );
+                }
+            }
+        })
+    }
+}
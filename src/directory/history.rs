@@ -0,0 +1,126 @@
+use std::{collections::HashSet, io, path::PathBuf};
+
+use eyre::{Context, OptionExt, Result, bail};
+use tantivy::directory::error::OpenReadError;
+
+use super::{MANAGED_JSON, META_JSON, RemoteDirectory, pinned};
+use crate::{Revision, utils::PathExt};
+
+impl RemoteDirectory {
+    /// Returns the revisions of `meta.json` which are retained, from the most recent to
+    /// the oldest.
+    ///
+    /// A revision is stored every time a commit is made, and is retained according to
+    /// [`Options::history`][1]. This fails if the metadata backend does not support
+    /// retaining revisions.
+    ///
+    /// [1]: crate::Options::history
+    pub async fn revisions(&self) -> Result<Vec<Revision>> {
+        self.metadata
+            .revisions()
+            .await
+            .wrap_err("failed to list revisions")
+    }
+
+    /// Reads the content of `meta.json` at the given revision, or returns `None` if it
+    /// has not been retained.
+    pub async fn read_revision(&self, version: i64) -> Result<Option<Vec<u8>>> {
+        let revision = self
+            .metadata
+            .read_revision(version)
+            .await
+            .wrap_err("failed to read revision")?;
+
+        Ok(revision.map(|revision| revision.content))
+    }
+
+    /// Makes the given revision of `meta.json` current again, along with the
+    /// `.managed.json` it was written with, returning the new version of `meta.json`.
+    ///
+    /// The files listed in that `.managed.json` which have been deleted since are
+    /// restored, and the files committed since are marked as deleted. This fails if the
+    /// revision has not been retained, or if any of the files to restore has already
+    /// been removed from the object storage.
+    ///
+    /// Readers have to be reloaded to see the rollback, and index writers which were
+    /// opened before it should not be used afterwards.
+    pub async fn rollback(&self, version: i64) -> Result<i64> {
+        if self.pinned.is_some() {
+            return Err(pinned::read_only()).wrap_err("failed to roll back");
+        }
+
+        let revision = self
+            .metadata
+            .read_revision(version)
+            .await
+            .wrap_err("failed to read revision")?
+            .ok_or_eyre("revision has not been retained")?;
+
+        let mut paths = Vec::new();
+        if let Some(managed) = &revision.managed {
+            let managed = serde_json::from_slice::<HashSet<PathBuf>>(managed)
+                .wrap_err("failed to parse managed files")?;
+
+            for path in managed {
+                let path = path.try_to_str::<io::Error>()?;
+                paths.push(path.to_owned());
+            }
+        }
+
+        let registered = self
+            .metadata
+            .registered(&paths)
+            .await
+            .wrap_err("failed to list registered files")?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        // Files which are not registered, or which have been marked as deleted, might
+        // have been removed by a garbage collection.
+        let mut restored = Vec::new();
+        for path in &paths {
+            if registered.contains(path) {
+                continue;
+            }
+
+            match self.operator.metadata(&self.path(path)).await {
+                Ok(metadata) => restored.push((path.clone(), metadata)),
+                Err(OpenReadError::FileDoesNotExist(_)) => {
+                    bail!("file {path} has already been removed")
+                }
+
+                Err(error) => return Err(error).wrap_err("failed to stat file"),
+            }
+        }
+
+        // The files committed since the revision are marked as deleted by the rollback.
+        let committed = self
+            .metadata
+            .files()
+            .await
+            .wrap_err("failed to list registered files")?;
+
+        let version = self
+            .metadata
+            .rollback(version, &paths, &restored, self.epoch())
+            .await
+            .wrap_err("failed to roll back")?
+            .ok_or_eyre("revision has not been retained")?;
+
+        // The caches might remember that the restored files do not exist, or that the
+        // removed ones do.
+        for path in paths.iter().chain(&committed) {
+            self.cache.invalidate(&self.path(path));
+        }
+
+        // `meta.json` can be written again without being read first, while
+        // `.managed.json` has to be read again as its version is not known.
+        self.versions
+            .upsert_async(META_JSON.to_path_buf(), Some(version))
+            .await;
+
+        self.versions.remove_async(*MANAGED_JSON).await;
+
+        Ok(version)
+    }
+}
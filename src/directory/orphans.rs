@@ -0,0 +1,159 @@
+use std::{
+    collections::HashSet,
+    io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use eyre::{Context, Result};
+
+use super::{GarbageCollection, MANAGED_JSON, RemoteDirectory};
+use crate::utils::PathExt;
+
+/// An object stored under the prefix of an index, which is not referenced by it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Orphan {
+    /// The path of the object in the object storage.
+    pub path: String,
+
+    /// The size of the object, in bytes.
+    pub size: u64,
+
+    /// When the object was last modified, if known.
+    pub last_modified: Option<SystemTime>,
+}
+
+impl RemoteDirectory {
+    /// Lists the objects stored under the prefix of the index which are not referenced
+    /// by it and were last modified at least `min_age` ago.
+    ///
+    /// An object is referenced if it is registered in PostgreSQL (even if it has been
+    /// marked as deleted), listed in the stored `.managed.json`, or written by this
+    /// directory and not committed yet. Objects whose age is unknown are only
+    /// considered to be orphans when `min_age` is zero.
+    ///
+    /// `min_age` should be longer than the time it takes for a writer to commit the
+    /// files it writes, as files which have not been committed yet by other writers
+    /// are not referenced.
+    pub async fn find_orphans(&self, min_age: Duration) -> Result<Vec<Orphan>> {
+        let referenced = self.referenced().await?;
+
+        let prefix = self.prefix();
+        let entries = self
+            .operator
+            .list_with(&prefix)
+            .recursive(true)
+            .await
+            .wrap_err("failed to list objects")?;
+
+        let now = SystemTime::now();
+        let mut orphans = Vec::new();
+        for entry in entries {
+            let metadata = entry.metadata();
+            if metadata.is_dir() {
+                continue;
+            }
+
+            let path = entry.path();
+            let Some(relative) = path.strip_prefix(&prefix) else {
+                continue;
+            };
+
+            if referenced.contains(relative) {
+                continue;
+            }
+
+            // Listing does not return the modification time with all services.
+            let mut metadata = metadata.clone();
+            if metadata.last_modified().is_none() {
+                metadata = self
+                    .operator
+                    .stat(path)
+                    .await
+                    .wrap_err("failed to stat object")?;
+            }
+
+            let last_modified = metadata.last_modified().map(SystemTime::from);
+            let old = match last_modified {
+                Some(last_modified) => {
+                    let age = now.duration_since(last_modified).unwrap_or_default();
+                    age >= min_age
+                }
+
+                None => min_age.is_zero(),
+            };
+
+            if old {
+                orphans.push(Orphan {
+                    path: path.to_owned(),
+                    size: metadata.content_length(),
+                    last_modified,
+                });
+            }
+        }
+
+        Ok(orphans)
+    }
+
+    /// Removes the objects returned by [`find_orphans()`][1] from the object storage.
+    ///
+    /// [1]: Self::find_orphans
+    pub async fn remove_orphans(&self, min_age: Duration) -> Result<GarbageCollection> {
+        let orphans = self.find_orphans(min_age).await?;
+
+        let collection = GarbageCollection {
+            objects: orphans.len() as u64,
+            bytes: orphans.iter().map(|orphan| orphan.size).sum(),
+        };
+
+        let paths = orphans.into_iter().map(|orphan| orphan.path);
+        self.operator
+            .delete_iter(paths)
+            .await
+            .wrap_err("failed to remove orphans")?;
+
+        Ok(collection)
+    }
+
+    /// Returns the paths of all the files referenced by the index.
+    async fn referenced(&self) -> Result<HashSet<String>> {
+        let mut referenced = self
+            .metadata
+            .files()
+            .await
+            .wrap_err("failed to list registered files")?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let managed = MANAGED_JSON.try_to_str::<io::Error>()?;
+        let managed = self
+            .metadata
+            .read(managed)
+            .await
+            .wrap_err("failed to read managed files")?;
+
+        if let Some(managed) = managed {
+            let managed = serde_json::from_slice::<HashSet<PathBuf>>(&managed)
+                .wrap_err("failed to parse managed files")?;
+
+            for path in managed {
+                let path = path.try_to_str::<io::Error>()?;
+                referenced.insert(path.to_owned());
+            }
+        }
+
+        for path in self.cache.unsynced().await {
+            let path = path.try_to_str::<io::Error>()?;
+            referenced.insert(path.to_owned());
+        }
+
+        self.unregistered
+            .iter_async(|path, _| {
+                referenced.insert(path.clone());
+                true
+            })
+            .await;
+
+        Ok(referenced)
+    }
+}
@@ -0,0 +1,89 @@
+use std::{io, sync::Arc};
+
+use eyre::{Context, OptionExt, Result};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::RemoteDirectory;
+use crate::{Options, metadata::RevisionContent};
+
+/// The revision of the metadata files a directory is pinned at.
+#[derive(Debug)]
+pub(crate) struct Pinned {
+    /// The version of `meta.json` the directory is pinned at.
+    pub version: i64,
+
+    /// The content of the metadata files at that version.
+    pub revision: RevisionContent,
+}
+
+impl RemoteDirectory {
+    /// Creates a new read-only directory serving the given index as it was when the
+    /// given version of `meta.json` was written.
+    ///
+    /// The revision must have been retained (see [`revisions()`][1]). Files which are
+    /// referenced by retained revisions are not removed by [`collect_garbage()`][2], so
+    /// they can be read even if they have been deleted since.
+    ///
+    /// ## Panics
+    ///
+    /// This will panic if called from outside of the context of a `tokio` runtime.
+    ///
+    /// [1]: Self::revisions
+    /// [2]: Self::collect_garbage
+    pub async fn open_at(
+        index: Uuid,
+        version: i64,
+        operator: opendal::Operator,
+        pool: PgPool,
+    ) -> Result<Self> {
+        Self::open_at_with_options(index, version, operator, pool, Options::default()).await
+    }
+
+    /// Creates a new read-only directory serving the given index as it was when the
+    /// given version of `meta.json` was written, configured using the given options.
+    ///
+    /// See [`open_at()`][1].
+    ///
+    /// ## Panics
+    ///
+    /// This will panic if called from outside of the context of a `tokio` runtime.
+    ///
+    /// [1]: Self::open_at
+    pub async fn open_at_with_options(
+        index: Uuid,
+        version: i64,
+        operator: opendal::Operator,
+        pool: PgPool,
+        options: Options,
+    ) -> Result<Self> {
+        let mut directory = Self::open_with_options(index, operator, pool, options).await?;
+
+        let revision = directory
+            .metadata
+            .read_revision(version)
+            .await
+            .wrap_err("failed to read revision")?
+            .ok_or_eyre("revision has not been retained")?;
+
+        directory.pinned = Some(Arc::new(Pinned { version, revision }));
+
+        Ok(directory)
+    }
+
+    /// Returns the version of `meta.json` the directory is pinned at, if it has been
+    /// opened using [`open_at()`][1].
+    ///
+    /// [1]: Self::open_at
+    pub fn pinned_at(&self) -> Option<i64> {
+        self.pinned.as_ref().map(|pinned| pinned.version)
+    }
+}
+
+/// Returns the error returned when trying to write to a pinned directory.
+pub(crate) fn read_only() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ReadOnlyFilesystem,
+        "the directory is pinned at a past revision and cannot be written to",
+    )
+}
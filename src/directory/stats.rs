@@ -0,0 +1,22 @@
+use eyre::{Context, Result};
+
+use super::RemoteDirectory;
+use crate::CommitStats;
+
+impl RemoteDirectory {
+    /// Returns the statistics of the commits whose revision of `meta.json` is retained,
+    /// from the most recent to the oldest.
+    ///
+    /// The statistics are computed from `meta.json` every time a commit is made, and
+    /// are stored in PostgreSQL so that they can also be queried using SQL. They are
+    /// removed along with their revision, according to [`Options::history`][1]. This
+    /// fails if the metadata backend does not support storing statistics.
+    ///
+    /// [1]: crate::Options::history
+    pub async fn stats(&self) -> Result<Vec<CommitStats>> {
+        self.metadata
+            .stats()
+            .await
+            .wrap_err("failed to list statistics")
+    }
+}
@@ -22,13 +22,24 @@ pub struct File {
     operator: Operator,
 
     path: String,
-    metadata: Arc<Metadata>,
+    metadata: Arc<FileMetadata>,
+}
+
+/// The metadata of a file, as registered in the metadata backend or as returned by the
+/// object storage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// The length of the file, in bytes.
+    pub length: u64,
+
+    /// The entity tag of the file in the object storage, if known.
+    pub etag: Option<String>,
 }
 
 impl File {
     pub(crate) fn open(
         path: impl Into<String>,
-        metadata: Arc<Metadata>,
+        metadata: Arc<FileMetadata>,
         rt: Handle,
         operator: Operator,
     ) -> Arc<dyn FileHandle> {
@@ -71,7 +82,16 @@ impl FileHandle for File {
 
 impl HasLen for File {
     fn len(&self) -> usize {
-        self.metadata.content_length() as usize
+        self.metadata.length as usize
+    }
+}
+
+impl From<Metadata> for FileMetadata {
+    fn from(metadata: Metadata) -> Self {
+        Self {
+            length: metadata.content_length(),
+            etag: metadata.etag().map(str::to_owned),
+        }
     }
 }
 
@@ -1,12 +1,29 @@
 mod cache;
 mod directory;
 mod file;
+mod lock;
 mod metadata;
 mod operator;
+mod options;
 mod utils;
+mod watch;
 mod writer;
 
-pub use self::directory::RemoteDirectory;
+pub use self::{
+    directory::{Commit, GarbageCollection, Orphan, RemoteDirectory},
+    file::FileMetadata,
+    lock::Locking,
+    metadata::{
+        CommitHook, CommitStats, DeletedFile, Expected, MemoryBackend, MetadataBackend,
+        ObjectStorageBackend, PostgresBackend, RegisteredFile, Revision, RevisionContent,
+        SchemaError, WriteError, migrate,
+    },
+    options::{CacheOptions, HistoryOptions, Options},
+    watch::Watching,
+};
+
+#[cfg(feature = "sqlite")]
+pub use self::metadata::SqliteBackend;
 
 #[cfg(test)]
 mod test;
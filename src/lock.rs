@@ -0,0 +1,112 @@
+mod advisory;
+mod lease;
+
+use std::{io, time::Duration};
+
+use tantivy::directory::{Lock, error::LockError};
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    task, time,
+};
+
+pub(crate) use self::{advisory::AdvisoryLock, lease::LeaseLock};
+
+/// Configures how locks are acquired by [`RemoteDirectory`][1].
+///
+/// [1]: crate::RemoteDirectory
+#[derive(Clone, Debug, Default)]
+pub enum Locking {
+    /// Locks are not acquired at all.
+    ///
+    /// It is up to the user of the directory to make sure that there can only be one
+    /// index writer using it at any given time.
+    #[default]
+    Disabled,
+
+    /// Locks are acquired using PostgreSQL advisory locks, keyed by the ID of the
+    /// index and the name of the lock, and held on a dedicated connection until they
+    /// are released.
+    Advisory,
+
+    /// Locks are acquired using leases stored in PostgreSQL, which are refreshed by a
+    /// background task every third of `ttl`.
+    ///
+    /// A lock whose lease has not been refreshed for longer than `ttl` can be taken
+    /// over, so that a writer which hangs does not prevent others from acquiring it.
+    ///
+    /// The `ttl` must be long enough for a third of it not to be zero.
+    Lease {
+        /// How long a lease stays valid without being refreshed.
+        ttl: Duration,
+    },
+}
+
+/// Decides whether acquiring a lock should be retried, mirroring the policy used by
+/// `tantivy`'s own directories.
+struct RetryPolicy {
+    /// The number of retries left.
+    retries: usize,
+
+    /// How long to wait between retries.
+    wait: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns the retry policy to use when acquiring the given lock.
+    fn new(lock: &Lock) -> Self {
+        if lock.is_blocking {
+            Self {
+                retries: 100,
+                wait: Duration::from_millis(100),
+            }
+        } else {
+            Self {
+                retries: 0,
+                wait: Duration::ZERO,
+            }
+        }
+    }
+
+    /// Waits before the next retry, returning `false` if there are no retries left.
+    async fn wait(&mut self) -> bool {
+        if self.retries == 0 {
+            return false;
+        }
+
+        self.retries -= 1;
+        time::sleep(self.wait).await;
+
+        true
+    }
+}
+
+/// Returns the name of the given lock.
+fn name(lock: &Lock) -> Result<&str, LockError> {
+    lock.filepath.to_str().ok_or_else(|| {
+        let error = io::Error::new(io::ErrorKind::InvalidFilename, "invalid lock name");
+        LockError::wrap_io_error(error)
+    })
+}
+
+/// Returns the key used to identify the given lock for the index in PostgreSQL.
+fn key(index: uuid::Uuid, lock: &Lock) -> Result<String, LockError> {
+    let name = name(lock)?;
+    Ok(format!("{index}/{name}"))
+}
+
+/// Runs the given future releasing a lock to completion on the runtime, so that the
+/// lock can be acquired again as soon as it has been dropped.
+///
+/// Like the other blocking methods of the directory, this must not be called from
+/// within asynchronous code running on a single-threaded runtime.
+fn release(rt: &Handle, release: impl Future<Output = ()>) {
+    match Handle::try_current().map(|current| current.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => task::block_in_place(|| rt.block_on(release)),
+        _ => rt.block_on(release),
+    }
+}
+
+/// Wraps the given error into a [`LockError`].
+fn wrap(error: sqlx::Error) -> LockError {
+    LockError::wrap_io_error(io::Error::other(error))
+}
@@ -0,0 +1,79 @@
+use sqlx::{PgPool, Postgres, pool::PoolConnection};
+use tantivy::directory::{Lock, error::LockError};
+use tokio::runtime::Handle;
+use uuid::Uuid;
+
+use super::{RetryPolicy, key, release, wrap};
+
+/// A lock held using a PostgreSQL advisory lock, which is released when dropped.
+pub(crate) struct AdvisoryLock {
+    rt: Handle,
+
+    /// The key identifying the lock.
+    key: String,
+
+    /// The connection on which the lock is held.
+    conn: Option<PoolConnection<Postgres>>,
+}
+
+impl AdvisoryLock {
+    /// Acquires the given lock for the index, on a connection dedicated to it.
+    ///
+    /// If the lock is blocking, this retries for a while before giving up.
+    pub async fn acquire(
+        index: Uuid,
+        lock: &Lock,
+        pool: &PgPool,
+        rt: Handle,
+    ) -> Result<Self, LockError> {
+        let key = key(index, lock)?;
+        let mut conn = pool.acquire().await.map_err(wrap)?;
+
+        let mut retry = RetryPolicy::new(lock);
+        loop {
+            let query = sqlx::query_scalar!(
+                r#"
+                SELECT pg_try_advisory_lock(hashtextextended($1, 0)) AS "acquired!"
+                "#,
+                key,
+            );
+
+            let acquired = query.fetch_one(&mut *conn).await.map_err(wrap)?;
+            if acquired {
+                return Ok(Self {
+                    rt,
+                    key,
+                    conn: Some(conn),
+                });
+            }
+
+            if !retry.wait().await {
+                return Err(LockError::LockBusy);
+            }
+        }
+    }
+}
+
+impl Drop for AdvisoryLock {
+    fn drop(&mut self) {
+        let Some(mut conn) = self.conn.take() else {
+            return;
+        };
+
+        let key = std::mem::take(&mut self.key);
+        release(&self.rt, async move {
+            let query = sqlx::query_scalar!(
+                r#"
+                SELECT pg_advisory_unlock(hashtextextended($1, 0)) AS "released!"
+                "#,
+                key,
+            );
+
+            // If the lock cannot be released, closing the connection releases it.
+            let released = query.fetch_one(&mut *conn).await;
+            if !matches!(released, Ok(true)) {
+                conn.close_on_drop();
+            }
+        });
+    }
+}
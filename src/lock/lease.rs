@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tantivy::directory::{Lock, error::LockError};
+use tokio::{runtime::Handle, task::JoinHandle, time};
+use uuid::Uuid;
+
+use super::{RetryPolicy, release, wrap};
+use crate::PostgresBackend;
+
+/// A lock held using a lease stored in PostgreSQL, which is kept alive by a background
+/// task and released when dropped.
+pub(crate) struct LeaseLock {
+    rt: Handle,
+    pool: PgPool,
+
+    /// The name of the table storing the leases.
+    table: String,
+
+    index: Uuid,
+    name: String,
+
+    /// The ID identifying this holder of the lock.
+    holder: Uuid,
+
+    /// The task refreshing the lease.
+    heartbeat: JoinHandle<()>,
+}
+
+impl LeaseLock {
+    /// Acquires the given lock for the index, taking it over if its current holder has
+    /// not refreshed its lease for longer than `ttl`.
+    ///
+    /// If the lock is blocking, this retries for a while before giving up.
+    pub async fn acquire(
+        index: Uuid,
+        lock: &Lock,
+        ttl: Duration,
+        backend: &PostgresBackend,
+        rt: Handle,
+    ) -> Result<Self, LockError> {
+        let pool = backend.pool();
+        let table = backend.tables().locks.clone();
+        let name = super::name(lock)?.to_owned();
+        let holder = Uuid::new_v4();
+
+        let sql = format!(
+            r#"
+            INSERT INTO {table} AS existing
+              (index, name, holder_id, last_alive_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (index, name)
+            DO UPDATE SET
+              holder_id = EXCLUDED.holder_id,
+              last_alive_at = EXCLUDED.last_alive_at
+            WHERE existing.last_alive_at < NOW() - make_interval(secs => $4)
+            RETURNING holder_id
+            "#
+        );
+
+        let mut retry = RetryPolicy::new(lock);
+        loop {
+            let query = sqlx::query_scalar::<_, Uuid>(&sql)
+                .bind(index)
+                .bind(&name)
+                .bind(holder)
+                .bind(ttl.as_secs_f64());
+
+            let acquired = query.fetch_optional(pool).await.map_err(wrap)?;
+            if acquired.is_some() {
+                let heartbeat = heartbeat(
+                    index,
+                    name.clone(),
+                    holder,
+                    ttl,
+                    pool.clone(),
+                    table.clone(),
+                );
+                let heartbeat = rt.spawn(heartbeat);
+
+                return Ok(Self {
+                    rt,
+                    pool: pool.clone(),
+                    table,
+                    index,
+                    name,
+                    holder,
+                    heartbeat,
+                });
+            }
+
+            if !retry.wait().await {
+                return Err(LockError::LockBusy);
+            }
+        }
+    }
+}
+
+/// Refreshes the lease every third of `ttl`, until it is lost.
+async fn heartbeat(
+    index: Uuid,
+    name: String,
+    holder: Uuid,
+    ttl: Duration,
+    pool: PgPool,
+    table: String,
+) {
+    let sql = format!(
+        r#"
+        UPDATE {table}
+        SET last_alive_at = NOW()
+        WHERE index = $1
+          AND name = $2
+          AND holder_id = $3
+        "#
+    );
+
+    let mut interval = time::interval(ttl / 3);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let query = sqlx::query(&sql).bind(index).bind(&name).bind(holder);
+
+        // Failing to refresh the lease is not fatal as long as it is refreshed before
+        // it expires, but the lease has been taken over if no row was updated.
+        if let Ok(result) = query.execute(&pool).await
+            && result.rows_affected() == 0
+        {
+            return;
+        }
+    }
+}
+
+impl Drop for LeaseLock {
+    fn drop(&mut self) {
+        self.heartbeat.abort();
+
+        let pool = self.pool.clone();
+        let index = self.index;
+        let name = std::mem::take(&mut self.name);
+        let holder = self.holder;
+
+        let sql = format!(
+            r#"
+            DELETE
+            FROM {table}
+            WHERE index = $1
+              AND name = $2
+              AND holder_id = $3
+            "#,
+            table = self.table,
+        );
+
+        release(&self.rt, async move {
+            let query = sqlx::query(&sql).bind(index).bind(name).bind(holder);
+
+            // If the lease cannot be released, it will expire.
+            let _ = query.execute(&pool).await;
+        });
+    }
+}
@@ -1,94 +1,484 @@
-use derive_more::Debug;
-use eyre::{Context, Result};
-use sqlx::PgPool;
+mod memory;
+mod object;
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use derive_more::{Debug, Display, Error, From};
+use eyre::{Result, bail};
+use sqlx::FromRow;
 use uuid::Uuid;
 
-/// Takes care of storing and retrieving metadata about indexes.
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteBackend;
+pub use self::{
+    memory::MemoryBackend,
+    object::ObjectStorageBackend,
+    postgres::{CommitHook, PostgresBackend, SchemaError, migrate},
+};
+use crate::FileMetadata;
+
+/// Stores the metadata of indexes: the metadata files written using `atomic_write()`,
+/// and the files which have been registered as being part of the indexes.
+///
+/// Only the methods required to read and write indexes, and to collect garbage, have
+/// to be implemented. The others are used by optional features, and fail by default.
+#[async_trait]
+pub trait MetadataBackend: fmt::Debug + Send + Sync + 'static {
+    /// Creates the given index if it does not exist yet.
+    async fn open(&self, index: Uuid) -> Result<()>;
+
+    /// Returns `true` if there is a metadata file stored at the given path.
+    async fn exists(&self, index: Uuid, path: &str) -> Result<bool> {
+        Ok(self.version(index, path).await?.is_some())
+    }
+
+    /// Reads the metadata file stored at the given path, along with its version.
+    ///
+    /// Returns `None` if the file does not exist.
+    async fn read(&self, index: Uuid, path: &str) -> Result<Option<(Vec<u8>, i64)>>;
+
+    /// Returns the version of the metadata file stored at the given path, which is
+    /// incremented every time it is written.
+    ///
+    /// Returns `None` if the file does not exist.
+    async fn version(&self, index: Uuid, path: &str) -> Result<Option<i64>> {
+        let read = self.read(index, path).await?;
+        Ok(read.map(|(_, version)| version))
+    }
+
+    /// Writes the given content at the given path, returning the new version of the
+    /// file.
+    ///
+    /// The write must be rejected if the file is not at the `expected` version, or if
+    /// an `epoch` is provided and the fencing epoch of the index has been incremented
+    /// past it (see [`fence()`][1]).
+    ///
+    /// [1]: Self::fence
+    async fn write(
+        &self,
+        index: Uuid,
+        path: &str,
+        content: &[u8],
+        epoch: Option<i64>,
+        expected: Expected,
+    ) -> Result<i64, WriteError>;
+
+    /// Registers the given files as being part of the index and writes the given content
+    /// at the given path, returning the new version of the file.
+    ///
+    /// This is used to write `meta.json` along with the files of the commit, which
+    /// should only be registered if the write succeeds. By default, this is not
+    /// atomic: the files are registered after the write succeeds, so they are left
+    /// unregistered if registering them fails, and are then registered by the next
+    /// commit. Backends supporting transactions should do both atomically instead.
+    async fn commit(
+        &self,
+        index: Uuid,
+        files: &[(String, FileMetadata)],
+        path: &str,
+        content: &[u8],
+        epoch: Option<i64>,
+        expected: Expected,
+    ) -> Result<i64, WriteError> {
+        let version = self.write(index, path, content, epoch, expected).await?;
+        if !files.is_empty() {
+            self.register(index, files).await?;
+        }
+
+        Ok(version)
+    }
+
+    /// Returns the file registered at the given path, or `None` if there is none.
+    async fn file(&self, index: Uuid, path: &str) -> Result<Option<RegisteredFile>>;
+
+    /// Registers the given files as being part of the index.
+    ///
+    /// Files which were previously marked as deleted must be restored.
+    async fn register(&self, index: Uuid, files: &[(String, FileMetadata)]) -> Result<()>;
+
+    /// Marks the file at the given path as deleted.
+    ///
+    /// If the file has not been registered yet, it must be registered as deleted.
+    async fn delete(&self, index: Uuid, path: &str) -> Result<()>;
+
+    /// Returns at most `limit` files which have been marked as deleted for longer than
+    /// the given grace period, from the ones deleted the longest ago.
+    async fn deleted(&self, index: Uuid, grace: Duration, limit: i64) -> Result<Vec<DeletedFile>>;
+
+    /// Removes the files at the given paths, if they are still marked as deleted.
+    async fn purge(&self, index: Uuid, paths: &[String]) -> Result<()>;
+
+    /// Returns the paths of all the files registered for the index, including the ones
+    /// which have been marked as deleted.
+    async fn files(&self, index: Uuid) -> Result<Vec<String>>;
+
+    /// Returns which of the given paths are registered and have not been marked as
+    /// deleted.
+    async fn registered(&self, index: Uuid, paths: &[String]) -> Result<Vec<String>> {
+        let mut registered = Vec::new();
+        for path in paths {
+            let file = self.file(index, path).await?;
+            if file.is_some_and(|file| !file.deleted) {
+                registered.push(path.clone());
+            }
+        }
+
+        Ok(registered)
+    }
+
+    /// Increments the fencing epoch of the index, returning the new epoch.
+    ///
+    /// This is called every time the index writer lock is acquired, so that writers
+    /// which lost the lock can be prevented from writing.
+    async fn fence(&self, index: Uuid) -> Result<i64> {
+        let _ = index;
+        bail!("fencing is not supported by this backend")
+    }
+
+    /// Returns the revisions of `meta.json` which are retained, from the most recent to
+    /// the oldest.
+    async fn revisions(&self, index: Uuid) -> Result<Vec<Revision>> {
+        let _ = index;
+        bail!("revisions are not supported by this backend")
+    }
+
+    /// Returns the statistics of the commits whose revision of `meta.json` is retained,
+    /// from the most recent to the oldest.
+    async fn stats(&self, index: Uuid) -> Result<Vec<CommitStats>> {
+        let _ = index;
+        bail!("statistics are not supported by this backend")
+    }
+
+    /// Reads the content of `meta.json` and `.managed.json` at the given revision.
+    ///
+    /// Returns `None` if the revision does not exist or has not been retained.
+    async fn read_revision(&self, index: Uuid, version: i64) -> Result<Option<RevisionContent>> {
+        let _ = (index, version);
+        bail!("revisions are not supported by this backend")
+    }
+
+    /// Makes the given revision of `meta.json` and `.managed.json` current again,
+    /// returning the new version of `meta.json`, or `None` if the revision has not been
+    /// retained.
+    ///
+    /// The given `restored` files must be registered again, restoring them if they
+    /// were marked as deleted, and every other registered file which is not one of the
+    /// given `paths` (listed in the `.managed.json` of the revision) must be marked as
+    /// deleted, atomically with the writes.
+    async fn rollback(
+        &self,
+        index: Uuid,
+        version: i64,
+        paths: &[String],
+        restored: &[(String, FileMetadata)],
+        epoch: Option<i64>,
+    ) -> Result<Option<i64>> {
+        let _ = (index, version, paths, restored, epoch);
+        bail!("revisions are not supported by this backend")
+    }
+
+    /// Returns the PostgreSQL backend used by the backend, if it uses one, which is
+    /// required for locking and watching.
+    fn postgres(&self) -> Option<&PostgresBackend> {
+        None
+    }
+}
+
+/// Takes care of storing and retrieving metadata about an index, using a
+/// [`MetadataBackend`].
 #[derive(Clone, Debug)]
-pub struct MetadataStore {
+pub(crate) struct MetadataStore {
     /// The ID of the index this is storing the metadata of.
     index: Uuid,
 
-    /// Pool of connections to interact with PSQL.
-    pool: PgPool,
+    /// The backend storing the metadata.
+    backend: Arc<dyn MetadataBackend>,
+}
+
+/// An error returned when writing a metadata file.
+#[derive(Debug, Display, Error, From)]
+pub enum WriteError {
+    /// The write was rejected because the index writer lock was acquired by another
+    /// writer after the one writing.
+    #[display("write rejected: fencing epoch {epoch} is stale")]
+    Fenced {
+        /// The fencing epoch of the writer.
+        epoch: i64,
+    },
+
+    /// The write was rejected because the file was not at the expected version, which
+    /// means that it has been written by someone else since it was last read.
+    #[display("write rejected: the file was modified since it was read")]
+    Conflict {
+        /// The version the file was expected to be at, or `None` if it was expected to
+        /// not exist.
+        expected: Option<i64>,
+    },
+
+    /// An error returned by the database.
+    #[display("database error: {_0}")]
+    #[from]
+    Database(sqlx::Error),
+
+    /// An error returned by the object storage.
+    #[display("storage error: {_0}")]
+    #[from]
+    Storage(opendal::Error),
+
+    /// Any other error returned by the backend.
+    #[display("{_0}")]
+    #[from]
+    Other(#[error(not(source))] eyre::Report),
+}
+
+/// The version a metadata file is expected to be at when it is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expected {
+    /// The file is written regardless of its version.
+    Any,
+
+    /// The file must not exist.
+    Missing,
+
+    /// The file must be at the given version.
+    Version(i64),
+}
+
+/// A revision of `meta.json`, stored every time it is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Revision {
+    /// The version of `meta.json` which was written, which increases with every write.
+    pub version: i64,
+
+    /// When the revision was written.
+    pub created_at: SystemTime,
+}
+
+/// The statistics of a commit, computed from the `meta.json` it wrote.
+///
+/// The statistics of a commit are removed along with its revision of `meta.json`, so
+/// they are retained according to [`HistoryOptions`][1] (the 10 most recent commits by
+/// default).
+///
+/// [1]: crate::HistoryOptions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitStats {
+    /// The version of `meta.json` which was written.
+    pub version: i64,
+
+    /// The opstamp of the commit.
+    pub opstamp: i64,
+
+    /// The number of segments of the index.
+    pub segments: i64,
+
+    /// The number of documents of the index, excluding deleted ones.
+    pub docs: i64,
+
+    /// The number of documents which have been deleted but not merged away yet.
+    pub deleted_docs: i64,
+
+    /// The payload of the commit, if any.
+    pub payload: Option<String>,
+
+    /// The MD5 hash of the schema of the index, serialized with its keys sorted.
+    pub schema_hash: String,
+
+    /// When the commit was made.
+    pub created_at: SystemTime,
+}
+
+/// The content of the metadata files at a given revision.
+#[derive(Clone, Debug, PartialEq, Eq, FromRow)]
+pub struct RevisionContent {
+    /// The content of `meta.json`.
+    #[debug(skip)]
+    pub content: Vec<u8>,
+
+    /// The content of `.managed.json` when `meta.json` was written, if it existed.
+    #[debug(skip)]
+    pub managed: Option<Vec<u8>>,
+}
+
+/// A file which has been marked as deleted, and can be removed from the object storage.
+#[derive(Clone, Debug, PartialEq, Eq, FromRow)]
+pub struct DeletedFile {
+    /// The path of the file, relative to the prefix of the index.
+    pub path: String,
+
+    /// The length of the file, in bytes.
+    ///
+    /// This is `None` for files which were registered before lengths were stored, or
+    /// which were deleted before being registered.
+    pub length: Option<i64>,
+}
+
+/// A file registered as being part of an index.
+#[derive(Clone, Debug, FromRow)]
+pub struct RegisteredFile {
+    /// Whether the file has been marked as deleted.
+    pub deleted: bool,
+
+    /// The length of the file, in bytes.
+    ///
+    /// This is `None` for files which were registered before lengths were stored, or
+    /// which were deleted before being registered.
+    pub length: Option<i64>,
+
+    /// The entity tag of the file in the object storage, if known.
+    pub etag: Option<String>,
 }
 
 impl MetadataStore {
     /// Creates a new metadata store for the given index.
     ///
     /// If the index does not exists, it creates it.
-    pub(crate) async fn open(index: Uuid, pool: PgPool) -> Result<Self> {
-        let create = sqlx::query!(
-            r#"
-            INSERT INTO tantivy.directories (index)
-            VALUES ($1)
-            ON CONFLICT DO NOTHING
-            "#,
-            index,
-        );
+    pub async fn open(index: Uuid, backend: Arc<dyn MetadataBackend>) -> Result<Self> {
+        backend.open(index).await?;
 
-        create
-            .execute(&pool)
-            .await
-            .wrap_err("failed to create index")?;
+        Ok(Self { index, backend })
+    }
 
-        Ok(Self { index, pool })
+    /// Returns the PostgreSQL backend used by the backend, if it uses one.
+    pub fn postgres(&self) -> Option<&PostgresBackend> {
+        self.backend.postgres()
     }
 
     /// Returns `true` if there is a file with the given path stored in the metadata
     /// store.
-    pub async fn exists(&self, path: &str) -> sqlx::Result<bool> {
-        let query = sqlx::query_scalar!(
-            r#"
-            SELECT 1
-            FROM tantivy.metadata
-            WHERE index = $1
-              AND path = $2
-            "#,
-            self.index,
-            path,
-        );
+    pub async fn exists(&self, path: &str) -> Result<bool> {
+        self.backend.exists(self.index, path).await
+    }
 
-        let row = query.fetch_optional(&self.pool).await?;
+    /// Reads the metadata file stored in the metadata store at the given path.
+    ///
+    /// Returns `None` if the file does not exist.
+    pub async fn read(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let read = self.backend.read(self.index, path).await?;
+        Ok(read.map(|(content, _)| content))
+    }
 
-        Ok(row.is_some())
+    /// Reads the metadata file stored in the metadata store at the given path, along
+    /// with its version.
+    ///
+    /// Returns `None` if the file does not exist.
+    pub async fn read_with_version(&self, path: &str) -> Result<Option<(Vec<u8>, i64)>> {
+        self.backend.read(self.index, path).await
     }
 
-    /// Reads the metadata file stored in the metadata store at the given path.
+    /// Returns the version of the metadata file stored at the given path.
     ///
     /// Returns `None` if the file does not exist.
-    pub async fn read(&self, path: &str) -> sqlx::Result<Option<Vec<u8>>> {
-        let query = sqlx::query_scalar!(
-            r#"
-            SELECT content
-            FROM tantivy.metadata
-            WHERE index = $1
-              AND path = $2
-            "#,
-            self.index,
-            path,
-        );
-
-        query.fetch_optional(&self.pool).await
-    }
-
-    /// Writes the given content to the metadata store at the given path.
-    pub async fn write(&self, path: &str, content: &[u8]) -> sqlx::Result<()> {
-        let query = sqlx::query!(
-            r#"
-            INSERT INTO tantivy.metadata
-              (index, path, content)
-            VALUES ($1, $2, $3)
-            ON CONFLICT (index, path)
-            DO UPDATE SET content = EXCLUDED.content
-            "#,
-            self.index,
-            path,
-            content,
-        );
-
-        query.execute(&self.pool).await?;
-
-        Ok(())
+    pub async fn version(&self, path: &str) -> Result<Option<i64>> {
+        self.backend.version(self.index, path).await
+    }
+
+    /// Increments the fencing epoch of the index, returning the new epoch.
+    pub async fn fence(&self) -> Result<i64> {
+        self.backend.fence(self.index).await
+    }
+
+    /// Writes the given content to the metadata store at the given path, returning the
+    /// new version of the file.
+    pub async fn write(
+        &self,
+        path: &str,
+        content: &[u8],
+        epoch: Option<i64>,
+        expected: Expected,
+    ) -> Result<i64, WriteError> {
+        let write = self
+            .backend
+            .write(self.index, path, content, epoch, expected);
+        write.await
+    }
+
+    /// Registers the given files as being part of the index and writes the given content
+    /// to the metadata store at the given path, atomically if the backend supports it,
+    /// returning the new version of the file.
+    pub async fn commit(
+        &self,
+        files: &[(String, FileMetadata)],
+        path: &str,
+        content: &[u8],
+        epoch: Option<i64>,
+        expected: Expected,
+    ) -> Result<i64, WriteError> {
+        let commit = self
+            .backend
+            .commit(self.index, files, path, content, epoch, expected);
+        commit.await
+    }
+
+    /// Returns the revisions of `meta.json` which are retained, from the most recent to
+    /// the oldest.
+    pub async fn revisions(&self) -> Result<Vec<Revision>> {
+        self.backend.revisions(self.index).await
+    }
+
+    /// Returns the statistics of the commits whose revision is retained, from the most
+    /// recent to the oldest.
+    pub async fn stats(&self) -> Result<Vec<CommitStats>> {
+        self.backend.stats(self.index).await
+    }
+
+    /// Reads the content of `meta.json` and `.managed.json` at the given revision.
+    pub async fn read_revision(&self, version: i64) -> Result<Option<RevisionContent>> {
+        self.backend.read_revision(self.index, version).await
+    }
+
+    /// Returns which of the given paths are registered and have not been marked as
+    /// deleted.
+    pub async fn registered(&self, paths: &[String]) -> Result<Vec<String>> {
+        self.backend.registered(self.index, paths).await
+    }
+
+    /// Makes the given revision of `meta.json` and `.managed.json` current again.
+    pub async fn rollback(
+        &self,
+        version: i64,
+        paths: &[String],
+        restored: &[(String, FileMetadata)],
+        epoch: Option<i64>,
+    ) -> Result<Option<i64>> {
+        let rollback = self
+            .backend
+            .rollback(self.index, version, paths, restored, epoch);
+
+        rollback.await
+    }
+
+    /// Returns the file registered at the given path, or `None` if there is none.
+    pub async fn file(&self, path: &str) -> Result<Option<RegisteredFile>> {
+        self.backend.file(self.index, path).await
+    }
+
+    /// Marks the file at the given path as deleted.
+    pub async fn delete(&self, path: &str) -> Result<()> {
+        self.backend.delete(self.index, path).await
+    }
+
+    /// Returns at most `limit` files which have been marked as deleted for longer than
+    /// the given grace period.
+    pub async fn deleted(&self, grace: Duration, limit: i64) -> Result<Vec<DeletedFile>> {
+        self.backend.deleted(self.index, grace, limit).await
+    }
+
+    /// Removes the files at the given paths, if they are still marked as deleted.
+    pub async fn purge(&self, paths: &[String]) -> Result<()> {
+        self.backend.purge(self.index, paths).await
+    }
+
+    /// Returns the paths of all the files registered for the index.
+    pub async fn files(&self) -> Result<Vec<String>> {
+        self.backend.files(self.index).await
     }
 }
@@ -0,0 +1,379 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+use derive_more::Debug;
+use eyre::{OptionExt, Result};
+use uuid::Uuid;
+
+use super::{
+    DeletedFile, Expected, MetadataBackend, RegisteredFile, Revision, RevisionContent, WriteError,
+};
+use crate::{
+    FileMetadata, HistoryOptions,
+    directory::{MANAGED_JSON, META_JSON},
+    utils::FastConcurrentMap,
+};
+
+/// A [`MetadataBackend`] storing the metadata in memory, meant for tests and indexes
+/// which do not have to outlive the process.
+///
+/// Clones of the backend share the same metadata, so that multiple directories can be
+/// opened on the same index. This backend does not support locking nor watching.
+#[derive(Clone, Debug, Default)]
+#[debug("MemoryBackend")]
+pub struct MemoryBackend {
+    /// The metadata of each index which has been opened.
+    indexes: Arc<FastConcurrentMap<Uuid, MemoryIndex>>,
+
+    /// Configures how many revisions of `meta.json` are retained.
+    history: HistoryOptions,
+}
+
+/// The metadata of an index stored in a [`MemoryBackend`].
+#[derive(Default)]
+struct MemoryIndex {
+    /// The fencing epoch of the index.
+    epoch: i64,
+
+    /// Contains, for each metadata file, its content and version.
+    metadata: HashMap<String, (Vec<u8>, i64)>,
+
+    /// Contains, for each registered file, whether it has been marked as deleted and
+    /// when.
+    files: HashMap<String, MemoryFile>,
+
+    /// Contains the retained revisions of `meta.json`, along with when they were
+    /// written.
+    revisions: BTreeMap<i64, (SystemTime, RevisionContent)>,
+}
+
+/// A file registered in a [`MemoryIndex`].
+struct MemoryFile {
+    /// When the file was marked as deleted, if it has been.
+    deleted_at: Option<Instant>,
+
+    /// The length of the file, in bytes, if known.
+    length: Option<i64>,
+
+    /// The entity tag of the file in the object storage, if known.
+    etag: Option<String>,
+}
+
+impl MemoryBackend {
+    /// Creates a new, empty, backend.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configures how many revisions of `meta.json` are retained.
+    pub fn with_history(mut self, history: HistoryOptions) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Calls the given closure with the metadata of the given index, failing if the
+    /// index has not been opened.
+    async fn with<R>(&self, index: Uuid, f: impl FnOnce(&mut MemoryIndex) -> R) -> Result<R> {
+        let update = self.indexes.update_async(&index, |_, index| f(index));
+        update.await.ok_or_eyre("index does not exist")
+    }
+}
+
+impl MemoryIndex {
+    /// Writes the given content at the given path, returning the new version of the
+    /// file.
+    ///
+    /// See [`write()`][1].
+    ///
+    /// [1]: MetadataBackend::write
+    fn write(
+        &mut self,
+        path: &str,
+        content: &[u8],
+        epoch: Option<i64>,
+        expected: Expected,
+        history: &HistoryOptions,
+    ) -> Result<i64, WriteError> {
+        if let Some(epoch) = epoch
+            && epoch < self.epoch
+        {
+            return Err(WriteError::Fenced { epoch });
+        }
+
+        let current = self.metadata.get(path).map(|(_, version)| *version);
+        match expected {
+            Expected::Any => {}
+            Expected::Missing if current.is_none() => {}
+            Expected::Version(version) if current == Some(version) => {}
+            Expected::Missing => return Err(WriteError::Conflict { expected: None }),
+            Expected::Version(version) => {
+                return Err(WriteError::Conflict {
+                    expected: Some(version),
+                });
+            }
+        }
+
+        let version = current.map_or(1, |version| version + 1);
+        self.metadata
+            .insert(path.to_owned(), (content.to_vec(), version));
+
+        if Path::new(path) == *META_JSON {
+            self.record(version, content, history);
+        }
+
+        Ok(version)
+    }
+
+    /// Stores the given content of `meta.json` as a new revision, along with the
+    /// current content of `.managed.json`, and then removes the revisions which
+    /// should not be retained anymore.
+    fn record(&mut self, version: i64, content: &[u8], history: &HistoryOptions) {
+        let managed = MANAGED_JSON
+            .to_str()
+            .expect("`.managed.json` is valid UTF-8");
+
+        let revision = RevisionContent {
+            content: content.to_vec(),
+            managed: self
+                .metadata
+                .get(managed)
+                .map(|(managed, _)| managed.clone()),
+        };
+
+        let now = SystemTime::now();
+        self.revisions.insert(version, (now, revision));
+
+        // The current revision is always retained.
+        self.revisions.retain(|&retained, (created_at, _)| {
+            let recent = history
+                .retain
+                .is_none_or(|retain| retained > version - retain as i64);
+
+            let young = history.max_age.is_none_or(|max_age| {
+                now.duration_since(*created_at).unwrap_or_default() <= max_age
+            });
+
+            retained == version || (recent && young)
+        });
+    }
+
+    /// Registers the given files, restoring them if they were marked as deleted.
+    fn register(&mut self, files: &[(String, FileMetadata)]) {
+        for (path, metadata) in files {
+            let file = MemoryFile {
+                deleted_at: None,
+                length: Some(metadata.length as i64),
+                etag: metadata.etag.clone(),
+            };
+
+            self.files.insert(path.clone(), file);
+        }
+    }
+}
+
+#[async_trait]
+impl MetadataBackend for MemoryBackend {
+    async fn open(&self, index: Uuid) -> Result<()> {
+        self.indexes.entry_async(index).await.or_default();
+        Ok(())
+    }
+
+    async fn read(&self, index: Uuid, path: &str) -> Result<Option<(Vec<u8>, i64)>> {
+        self.with(index, |index| index.metadata.get(path).cloned())
+            .await
+    }
+
+    async fn write(
+        &self,
+        index: Uuid,
+        path: &str,
+        content: &[u8],
+        epoch: Option<i64>,
+        expected: Expected,
+    ) -> Result<i64, WriteError> {
+        let write = self.indexes.update_async(&index, |_, index| {
+            index.write(path, content, epoch, expected, &self.history)
+        });
+
+        // This mirrors what happens with PostgreSQL when the index does not exist.
+        write
+            .await
+            .unwrap_or(Err(WriteError::Database(sqlx::Error::RowNotFound)))
+    }
+
+    async fn file(&self, index: Uuid, path: &str) -> Result<Option<RegisteredFile>> {
+        self.with(index, |index| {
+            index.files.get(path).map(|file| RegisteredFile {
+                deleted: file.deleted_at.is_some(),
+                length: file.length,
+                etag: file.etag.clone(),
+            })
+        })
+        .await
+    }
+
+    async fn register(&self, index: Uuid, files: &[(String, FileMetadata)]) -> Result<()> {
+        self.with(index, |index| index.register(files)).await
+    }
+
+    async fn delete(&self, index: Uuid, path: &str) -> Result<()> {
+        self.with(index, |index| {
+            let file = index
+                .files
+                .entry(path.to_owned())
+                .or_insert_with(|| MemoryFile {
+                    deleted_at: None,
+                    length: None,
+                    etag: None,
+                });
+
+            file.deleted_at.get_or_insert_with(Instant::now);
+        })
+        .await
+    }
+
+    /// Files which are listed in the `.managed.json` of a retained revision are never
+    /// returned, so that they can still be rolled back to.
+    async fn deleted(&self, index: Uuid, grace: Duration, limit: i64) -> Result<Vec<DeletedFile>> {
+        self.with(index, |index| {
+            let referenced = index
+                .revisions
+                .values()
+                .filter_map(|(_, revision)| revision.managed.as_deref())
+                .filter_map(|managed| serde_json::from_slice::<Vec<String>>(managed).ok())
+                .flatten()
+                .collect::<HashSet<_>>();
+
+            let mut deleted = index
+                .files
+                .iter()
+                .filter_map(|(path, file)| Some((file.deleted_at?, path, file.length)))
+                .filter(|(deleted_at, path, _)| {
+                    deleted_at.elapsed() >= grace && !referenced.contains(*path)
+                })
+                .collect::<Vec<_>>();
+
+            deleted.sort_unstable();
+            deleted
+                .into_iter()
+                .take(limit as usize)
+                .map(|(_, path, length)| DeletedFile {
+                    path: path.clone(),
+                    length,
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn purge(&self, index: Uuid, paths: &[String]) -> Result<()> {
+        self.with(index, |index| {
+            for path in paths {
+                if index
+                    .files
+                    .get(path)
+                    .is_some_and(|file| file.deleted_at.is_some())
+                {
+                    index.files.remove(path);
+                }
+            }
+        })
+        .await
+    }
+
+    async fn files(&self, index: Uuid) -> Result<Vec<String>> {
+        self.with(index, |index| index.files.keys().cloned().collect())
+            .await
+    }
+
+    async fn fence(&self, index: Uuid) -> Result<i64> {
+        self.with(index, |index| {
+            index.epoch += 1;
+            index.epoch
+        })
+        .await
+    }
+
+    async fn revisions(&self, index: Uuid) -> Result<Vec<Revision>> {
+        self.with(index, |index| {
+            index
+                .revisions
+                .iter()
+                .rev()
+                .map(|(&version, (created_at, _))| Revision {
+                    version,
+                    created_at: *created_at,
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn read_revision(&self, index: Uuid, version: i64) -> Result<Option<RevisionContent>> {
+        self.with(index, |index| {
+            let revision = index.revisions.get(&version);
+            revision.map(|(_, revision)| revision.clone())
+        })
+        .await
+    }
+
+    async fn rollback(
+        &self,
+        index: Uuid,
+        version: i64,
+        paths: &[String],
+        restored: &[(String, FileMetadata)],
+        epoch: Option<i64>,
+    ) -> Result<Option<i64>> {
+        let rollback = self.with(index, |index| {
+            let Some((_, revision)) = index.revisions.get(&version) else {
+                return Ok(None);
+            };
+
+            let revision = revision.clone();
+
+            // Nothing is changed if the write is going to be rejected.
+            if let Some(epoch) = epoch
+                && epoch < index.epoch
+            {
+                return Err(WriteError::Fenced { epoch });
+            }
+
+            index.register(restored);
+
+            // The files which have been committed since the revision are not
+            // referenced anymore, as if tantivy had removed them.
+            if revision.managed.is_some() {
+                let paths = paths.iter().collect::<HashSet<_>>();
+                let now = Instant::now();
+                for (path, file) in &mut index.files {
+                    if !paths.contains(path) {
+                        file.deleted_at.get_or_insert(now);
+                    }
+                }
+            }
+
+            // `.managed.json` is written first, so that it is recorded along with the
+            // new revision of `meta.json`.
+            if let Some(managed) = &revision.managed {
+                let path = MANAGED_JSON
+                    .to_str()
+                    .expect("`.managed.json` is valid UTF-8");
+                index.write(path, managed, epoch, Expected::Any, &self.history)?;
+            }
+
+            let path = META_JSON.to_str().expect("`meta.json` is valid UTF-8");
+            let version =
+                index.write(path, &revision.content, epoch, Expected::Any, &self.history)?;
+
+            Ok(Some(version))
+        });
+
+        Ok(rollback.await??)
+    }
+}
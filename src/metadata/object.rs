@@ -0,0 +1,353 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use derive_more::Debug;
+use eyre::{Context, Result, bail};
+use opendal::{ErrorKind, Operator};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{DeletedFile, Expected, MetadataBackend, RegisteredFile, WriteError};
+use crate::FileMetadata;
+
+/// The name of the object listing the files registered for an index.
+const FILES: &str = "files.json";
+
+/// The number of bytes used to store the version of a metadata file, at the start of
+/// its object.
+const VERSION_LEN: usize = size_of::<i64>();
+
+/// A [`MetadataBackend`] storing the metadata as objects, using an [`opendal`]
+/// operator, for deployments without any database.
+///
+/// The metadata of an index is stored under `meta-{index}/`, which is distinct from
+/// the prefix under which its files are stored, so the same operator can be used for
+/// both. Writes are made atomic using conditional writes (`If-Match` and
+/// `If-None-Match`), which the service used by the operator must support.
+///
+/// This backend does not support locking, watching, nor retaining revisions of
+/// `meta.json`.
+#[derive(Clone, Debug)]
+#[debug("ObjectStorageBackend")]
+pub struct ObjectStorageBackend {
+    /// The operator used to read and write the objects.
+    operator: Operator,
+}
+
+/// A file registered for an index, as stored in [`FILES`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct StoredFile {
+    /// When the file was marked as deleted, as the number of seconds since the Unix
+    /// epoch, if it has been.
+    deleted_at: Option<f64>,
+
+    /// The length of the file, in bytes, if known.
+    length: Option<i64>,
+
+    /// The entity tag of the file in the object storage, if known.
+    etag: Option<String>,
+}
+
+/// The files registered for an index, keyed by their paths.
+type StoredFiles = BTreeMap<String, StoredFile>;
+
+impl ObjectStorageBackend {
+    /// Creates a new backend storing the metadata using the given operator.
+    ///
+    /// Fails if the service used by the operator does not support the conditional
+    /// writes required to update the metadata atomically.
+    pub fn new(operator: Operator) -> Result<Self> {
+        let capability = operator.info().full_capability();
+        if !capability.write_with_if_match || !capability.write_with_if_not_exists {
+            bail!("the object storage service does not support conditional writes");
+        }
+
+        Ok(Self { operator })
+    }
+
+    /// Returns the path of the object storing the file at the given path for the
+    /// index.
+    fn path(index: Uuid, path: &str) -> String {
+        format!("meta-{index}/{path}")
+    }
+
+    /// Reads the object at the given path, along with its entity tag.
+    ///
+    /// Returns `None` if the object does not exist.
+    async fn get(&self, path: &str) -> opendal::Result<Option<(Vec<u8>, String)>> {
+        loop {
+            let Some(etag) = self.etag(path).await? else {
+                return Ok(None);
+            };
+
+            let content = match self.operator.read(path).await {
+                Ok(content) => content.to_vec(),
+                Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                Err(error) => return Err(error),
+            };
+
+            // The object might have been written between being stat'ed and read, in
+            // which case this has to start over. Writes are conditional, so an object
+            // read along with a stale entity tag could not be overwritten anyway.
+            if self.etag(path).await?.as_deref() == Some(&*etag) {
+                return Ok(Some((content, etag)));
+            }
+        }
+    }
+
+    /// Returns the entity tag of the object at the given path.
+    ///
+    /// Returns `None` if the object does not exist.
+    async fn etag(&self, path: &str) -> opendal::Result<Option<String>> {
+        match self.operator.stat(path).await {
+            Ok(metadata) => match metadata.etag() {
+                Some(etag) => Ok(Some(etag.to_owned())),
+                None => {
+                    let message = "the object storage service did not return an etag";
+                    Err(opendal::Error::new(ErrorKind::Unsupported, message))
+                }
+            },
+
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Writes the given content at the given path, if the object is still at the
+    /// given entity tag, or if it does not exist when none is given.
+    ///
+    /// Returns `false` if the object has been written by someone else.
+    async fn put(&self, path: &str, content: Vec<u8>, etag: Option<&str>) -> opendal::Result<bool> {
+        let write = self.operator.write_with(path, content);
+        let write = match etag {
+            Some(etag) => write.if_match(etag),
+            None => write.if_not_exists(true),
+        };
+
+        match write.await {
+            Ok(_) => Ok(true),
+            Err(error) if is_conflict(&error) => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Reads the files registered for the index.
+    async fn files_of(&self, index: Uuid) -> Result<(StoredFiles, Option<String>)> {
+        let path = Self::path(index, FILES);
+        let Some((content, etag)) = self.get(&path).await? else {
+            return Ok((StoredFiles::default(), None));
+        };
+
+        let files = serde_json::from_slice(&content).wrap_err("failed to parse files")?;
+
+        Ok((files, Some(etag)))
+    }
+
+    /// Updates the files registered for the index using the given closure, retrying
+    /// until nobody else has updated them in the meantime.
+    async fn update_files(
+        &self,
+        index: Uuid,
+        mut update: impl FnMut(&mut StoredFiles),
+    ) -> Result<()> {
+        let path = Self::path(index, FILES);
+        loop {
+            let (mut files, etag) = self.files_of(index).await?;
+            update(&mut files);
+
+            let content = serde_json::to_vec(&files)?;
+            if self.put(&path, content, etag.as_deref()).await? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MetadataBackend for ObjectStorageBackend {
+    /// Indexes do not have to be created, as their metadata is stored lazily.
+    async fn open(&self, _index: Uuid) -> Result<()> {
+        Ok(())
+    }
+
+    async fn read(&self, index: Uuid, path: &str) -> Result<Option<(Vec<u8>, i64)>> {
+        let path = Self::path(index, path);
+        let read = self.operator.read(&path).await;
+        let mut content = match read {
+            Ok(content) => content.to_vec(),
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error).wrap_err("failed to read metadata file"),
+        };
+
+        let split = split(&mut content).wrap_err("failed to read metadata file")?;
+
+        Ok(Some(split))
+    }
+
+    /// Fencing is not supported, so the epoch is ignored: it is only provided when
+    /// locking is enabled, which requires PostgreSQL.
+    async fn write(
+        &self,
+        index: Uuid,
+        path: &str,
+        content: &[u8],
+        _epoch: Option<i64>,
+        expected: Expected,
+    ) -> Result<i64, WriteError> {
+        let path = Self::path(index, path);
+        loop {
+            let current = self.get(&path).await?;
+            let (version, etag) = match current {
+                Some((mut current, etag)) => {
+                    let (_, version) = split(&mut current)?;
+                    (Some(version), Some(etag))
+                }
+
+                None => (None, None),
+            };
+
+            match expected {
+                Expected::Any => {}
+                Expected::Missing if version.is_none() => {}
+                Expected::Version(expected) if version == Some(expected) => {}
+                Expected::Missing => return Err(WriteError::Conflict { expected: None }),
+                Expected::Version(expected) => {
+                    return Err(WriteError::Conflict {
+                        expected: Some(expected),
+                    });
+                }
+            }
+
+            let version = version.map_or(1, |version| version + 1);
+            let mut object = Vec::with_capacity(VERSION_LEN + content.len());
+            object.extend_from_slice(&version.to_be_bytes());
+            object.extend_from_slice(content);
+
+            if self.put(&path, object, etag.as_deref()).await? {
+                return Ok(version);
+            }
+
+            // Someone else wrote the file since it was read, which is only a conflict
+            // if a specific version was expected.
+            match expected {
+                Expected::Any => continue,
+                Expected::Missing => return Err(WriteError::Conflict { expected: None }),
+                Expected::Version(expected) => {
+                    return Err(WriteError::Conflict {
+                        expected: Some(expected),
+                    });
+                }
+            }
+        }
+    }
+
+    async fn file(&self, index: Uuid, path: &str) -> Result<Option<RegisteredFile>> {
+        let (files, _) = self.files_of(index).await?;
+        let file = files.get(path).map(|file| RegisteredFile {
+            deleted: file.deleted_at.is_some(),
+            length: file.length,
+            etag: file.etag.clone(),
+        });
+
+        Ok(file)
+    }
+
+    async fn register(&self, index: Uuid, files: &[(String, FileMetadata)]) -> Result<()> {
+        self.update_files(index, |stored| {
+            for (path, metadata) in files {
+                let file = StoredFile {
+                    deleted_at: None,
+                    length: Some(metadata.length as i64),
+                    etag: metadata.etag.clone(),
+                };
+
+                stored.insert(path.clone(), file);
+            }
+        })
+        .await
+    }
+
+    async fn delete(&self, index: Uuid, path: &str) -> Result<()> {
+        let now = now();
+        self.update_files(index, |files| {
+            let file = files.entry(path.to_owned()).or_default();
+            file.deleted_at.get_or_insert(now);
+        })
+        .await
+    }
+
+    async fn deleted(&self, index: Uuid, grace: Duration, limit: i64) -> Result<Vec<DeletedFile>> {
+        let (files, _) = self.files_of(index).await?;
+
+        let before = now() - grace.as_secs_f64();
+        let mut deleted = files
+            .iter()
+            .filter_map(|(path, file)| Some((file.deleted_at?, path, file.length)))
+            .filter(|(deleted_at, _, _)| *deleted_at <= before)
+            .collect::<Vec<_>>();
+
+        deleted.sort_unstable_by(|(a, _, _), (b, _, _)| a.total_cmp(b));
+
+        let deleted = deleted
+            .into_iter()
+            .take(limit as usize)
+            .map(|(_, path, length)| DeletedFile {
+                path: path.clone(),
+                length,
+            })
+            .collect();
+
+        Ok(deleted)
+    }
+
+    async fn purge(&self, index: Uuid, paths: &[String]) -> Result<()> {
+        self.update_files(index, |files| {
+            for path in paths {
+                if files
+                    .get(path)
+                    .is_some_and(|file| file.deleted_at.is_some())
+                {
+                    files.remove(path);
+                }
+            }
+        })
+        .await
+    }
+
+    async fn files(&self, index: Uuid) -> Result<Vec<String>> {
+        let (files, _) = self.files_of(index).await?;
+        Ok(files.into_keys().collect())
+    }
+}
+
+/// Returns whether the given error was caused by a conditional write whose condition
+/// did not hold.
+fn is_conflict(error: &opendal::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::ConditionNotMatch | ErrorKind::AlreadyExists
+    )
+}
+
+/// Splits the version stored at the start of the given object from the content of
+/// the metadata file.
+fn split(object: &mut Vec<u8>) -> opendal::Result<(Vec<u8>, i64)> {
+    if object.len() < VERSION_LEN {
+        let message = "metadata file is truncated";
+        return Err(opendal::Error::new(ErrorKind::Unexpected, message));
+    }
+
+    let content = object.split_off(VERSION_LEN);
+    let version = i64::from_be_bytes(object[..].try_into().expect("the version is 8 bytes long"));
+
+    Ok((content, version))
+}
+
+/// Returns the number of seconds since the Unix epoch.
+fn now() -> f64 {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+    now.unwrap_or_default().as_secs_f64()
+}
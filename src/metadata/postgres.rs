@@ -0,0 +1,852 @@
+mod compression;
+mod hook;
+mod schema;
+mod stats;
+mod tables;
+
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use eyre::{Context, Result};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+pub use self::{
+    hook::CommitHook,
+    schema::{SchemaError, migrate},
+};
+use self::{stats::Stats, tables::Tables};
+use super::{
+    CommitStats, DeletedFile, Expected, MetadataBackend, RegisteredFile, Revision, RevisionContent,
+    WriteError,
+};
+#[cfg(feature = "notify")]
+use crate::watch::CHANNEL;
+use crate::{
+    FileMetadata, HistoryOptions,
+    directory::{MANAGED_JSON, META_JSON},
+};
+
+/// A [`MetadataBackend`] storing the metadata in PostgreSQL, in the tables created by
+/// the migrations of this crate (see [`migrate()`]).
+///
+/// Opening an index fails with a [`SchemaError`] if the database has not been migrated
+/// to the version of the schema required by this crate.
+///
+/// By default, the tables are expected to be in the `tantivy` schema, which can be
+/// changed using [`with_schema()`][1], along with the prefix of their names using
+/// [`with_table_prefix()`][2]. The tables must then have been created beforehand, in
+/// the same way as the migrations do, including their foreign keys: the statistics of
+/// commits are removed along with their revisions by cascading deletes.
+///
+/// This is the only backend supporting locking, watching, retaining revisions of
+/// `meta.json`, and storing the statistics of commits. Application state can also be
+/// committed in the same transaction as `meta.json` using [`with_commit_hook()`][3].
+///
+/// The metadata files are stored compressed using zstd, unless disabled using
+/// [`with_compression()`][4], and files stored uncompressed are always readable.
+///
+/// [1]: Self::with_schema
+/// [2]: Self::with_table_prefix
+/// [3]: Self::with_commit_hook
+/// [4]: Self::with_compression
+#[derive(Clone, Debug)]
+pub struct PostgresBackend {
+    /// Pool of connections to interact with PSQL.
+    pool: PgPool,
+
+    /// The names of the tables used to store the metadata.
+    tables: Arc<Tables>,
+
+    /// Configures how many revisions of `meta.json` are retained.
+    history: HistoryOptions,
+
+    /// The hook called in the transaction writing `meta.json`, if any.
+    hook: Option<Arc<dyn CommitHook>>,
+
+    /// Whether the metadata files are compressed when written.
+    compression: bool,
+}
+
+impl PostgresBackend {
+    /// Creates a new backend storing the metadata using the given pool of connections.
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            tables: Arc::default(),
+            history: HistoryOptions::default(),
+            hook: None,
+            compression: true,
+        }
+    }
+
+    /// Configures the schema containing the tables, which defaults to `tantivy`.
+    pub fn with_schema(mut self, schema: &str) -> Self {
+        self.tables = Arc::new(Tables::new(schema, &self.tables.prefix));
+        self
+    }
+
+    /// Configures the prefix of the names of the tables, which defaults to none.
+    pub fn with_table_prefix(mut self, prefix: &str) -> Self {
+        self.tables = Arc::new(Tables::new(&self.tables.schema, prefix));
+        self
+    }
+
+    /// Configures how many revisions of `meta.json` are retained.
+    pub fn with_history(mut self, history: HistoryOptions) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Configures a hook called in the transaction writing `meta.json`, every time it is
+    /// written.
+    pub fn with_commit_hook(mut self, hook: impl CommitHook) -> Self {
+        self.hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Configures whether the metadata files are compressed when written, which they
+    /// are by default.
+    ///
+    /// This can be disabled while older versions of this crate, which cannot read
+    /// compressed files, are still reading the same indexes.
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Returns the pool of connections used to interact with PSQL.
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Returns the names of the tables used to store the metadata.
+    pub(crate) fn tables(&self) -> &Tables {
+        &self.tables
+    }
+
+    /// Writes the given content at the given path using the given connection, returning
+    /// the new version of the file.
+    ///
+    /// See [`write()`][1].
+    ///
+    /// [1]: MetadataBackend::write
+    async fn write_in(
+        &self,
+        conn: &mut PgConnection,
+        index: Uuid,
+        path: &str,
+        content: &[u8],
+        epoch: Option<i64>,
+        expected: Expected,
+    ) -> Result<i64, WriteError> {
+        let Tables {
+            directories,
+            metadata,
+            ..
+        } = &*self.tables;
+
+        let stored = compression::compress(content, self.compression)
+            .map_err(|error| WriteError::Other(error.into()))?;
+
+        let (check, expected) = match expected {
+            Expected::Any => (false, None),
+            Expected::Missing => (true, None),
+            Expected::Version(version) => (true, Some(version)),
+        };
+
+        // Locking the row of the index makes sure that the epoch cannot be incremented
+        // until the write has been committed.
+        let sql = format!(
+            r#"
+            WITH fence AS (
+              SELECT index
+              FROM {directories}
+              WHERE index = $1
+                AND ($4::BIGINT IS NULL OR epoch <= $4)
+              FOR SHARE
+            )
+            INSERT INTO {metadata} AS existing
+              (index, path, content)
+            SELECT index, $2, $3
+            FROM fence
+            ON CONFLICT (index, path)
+            DO UPDATE SET
+              content = EXCLUDED.content,
+              version = existing.version + 1
+            WHERE NOT $5
+               OR existing.version = $6
+            RETURNING version
+            "#
+        );
+
+        let query = sqlx::query_scalar::<_, i64>(&sql)
+            .bind(index)
+            .bind(path)
+            .bind(&*stored)
+            .bind(epoch)
+            .bind(check)
+            .bind(expected);
+
+        let Some(version) = query.fetch_optional(&mut *conn).await? else {
+            let sql = format!(
+                r#"
+                SELECT epoch
+                FROM {directories}
+                WHERE index = $1
+                "#
+            );
+
+            let query = sqlx::query_scalar::<_, i64>(&sql).bind(index);
+            let current = query.fetch_one(&mut *conn).await?;
+
+            return match epoch {
+                Some(epoch) if epoch < current => Err(WriteError::Fenced { epoch }),
+                _ => Err(WriteError::Conflict { expected }),
+            };
+        };
+
+        if Path::new(path) == *META_JSON {
+            self.record(conn, index, version, &stored).await?;
+            self.record_stats(conn, index, version, content).await?;
+
+            if let Some(hook) = &self.hook {
+                hook.on_commit(index, version, conn).await?;
+            }
+        }
+
+        // The notification is only delivered once the transaction is committed.
+        #[cfg(feature = "notify")]
+        if Path::new(path) == *META_JSON {
+            let query = sqlx::query!(
+                r#"
+                SELECT pg_notify($1, $2)
+                "#,
+                CHANNEL,
+                index.to_string(),
+            );
+
+            query.execute(&mut *conn).await?;
+        }
+
+        Ok(version)
+    }
+
+    /// Stores the given content of `meta.json` as a new revision, along with the
+    /// current content of `.managed.json`, and then removes the revisions which
+    /// should not be retained anymore.
+    async fn record(
+        &self,
+        conn: &mut PgConnection,
+        index: Uuid,
+        version: i64,
+        content: &[u8],
+    ) -> sqlx::Result<()> {
+        let Tables {
+            metadata,
+            revisions,
+            ..
+        } = &*self.tables;
+
+        let sql = format!(
+            r#"
+            INSERT INTO {revisions}
+              (index, version, content, managed)
+            SELECT $1, $2, $3, (
+              SELECT content
+              FROM {metadata}
+              WHERE index = $1
+                AND path = '.managed.json'
+            )
+            "#
+        );
+
+        let insert = sqlx::query(&sql).bind(index).bind(version).bind(content);
+        insert.execute(&mut *conn).await?;
+
+        let retain = self.history.retain.map(|retain| retain as i64);
+        let max_age = self.history.max_age.map(|max_age| max_age.as_secs_f64());
+
+        // The current revision is always retained.
+        let sql = format!(
+            r#"
+            DELETE
+            FROM {revisions}
+            WHERE index = $1
+              AND version < $2
+              AND (
+                version <= $2 - $3
+                OR created_at < NOW() - make_interval(secs => $4)
+              )
+            "#
+        );
+
+        let prune = sqlx::query(&sql)
+            .bind(index)
+            .bind(version)
+            .bind(retain)
+            .bind(max_age);
+
+        prune.execute(&mut *conn).await?;
+
+        Ok(())
+    }
+
+    /// Stores the statistics of the commit which wrote the given content of `meta.json`,
+    /// which are removed along with its revision.
+    ///
+    /// Nothing is stored if the content cannot be parsed.
+    async fn record_stats(
+        &self,
+        conn: &mut PgConnection,
+        index: Uuid,
+        version: i64,
+        content: &[u8],
+    ) -> sqlx::Result<()> {
+        let Some(stats) = Stats::parse(content) else {
+            return Ok(());
+        };
+
+        let sql = format!(
+            r#"
+            INSERT INTO {stats}
+              (index, version, opstamp, segments, docs, deleted_docs, payload, schema_hash)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, md5($8))
+            "#,
+            stats = self.tables.stats,
+        );
+
+        let insert = sqlx::query(&sql)
+            .bind(index)
+            .bind(version)
+            .bind(stats.opstamp)
+            .bind(stats.segments)
+            .bind(stats.docs)
+            .bind(stats.deleted_docs)
+            .bind(stats.payload)
+            .bind(stats.schema);
+
+        insert.execute(conn).await?;
+
+        Ok(())
+    }
+
+    /// Registers the given files using the given connection.
+    ///
+    /// See [`register()`][1].
+    ///
+    /// [1]: MetadataBackend::register
+    async fn register_in(
+        &self,
+        conn: &mut PgConnection,
+        index: Uuid,
+        files: &[(String, FileMetadata)],
+    ) -> sqlx::Result<()> {
+        let mut paths = Vec::with_capacity(files.len());
+        let mut lengths = Vec::with_capacity(files.len());
+        let mut etags = Vec::with_capacity(files.len());
+        for (path, metadata) in files {
+            paths.push(path.clone());
+            lengths.push(metadata.length as i64);
+            etags.push(metadata.etag.clone());
+        }
+
+        let sql = format!(
+            r#"
+            INSERT INTO {files}
+              (index, path, length, etag)
+            SELECT $1, *
+            FROM UNNEST($2::TEXT[], $3::BIGINT[], $4::TEXT[])
+            ON CONFLICT (index, path)
+            DO UPDATE SET
+              deleted = FALSE,
+              deleted_at = NULL,
+              length = EXCLUDED.length,
+              etag = EXCLUDED.etag
+            "#,
+            files = self.tables.files,
+        );
+
+        let query = sqlx::query(&sql)
+            .bind(index)
+            .bind(paths)
+            .bind(lengths)
+            .bind(etags);
+
+        query.execute(conn).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MetadataBackend for PostgresBackend {
+    async fn open(&self, index: Uuid) -> Result<()> {
+        schema::check(&self.pool, &self.tables).await?;
+
+        let sql = format!(
+            r#"
+            INSERT INTO {directories} (index)
+            VALUES ($1)
+            ON CONFLICT DO NOTHING
+            "#,
+            directories = self.tables.directories,
+        );
+
+        sqlx::query(&sql)
+            .bind(index)
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to create index")?;
+
+        Ok(())
+    }
+
+    async fn exists(&self, index: Uuid, path: &str) -> Result<bool> {
+        Ok(self.version(index, path).await?.is_some())
+    }
+
+    async fn read(&self, index: Uuid, path: &str) -> Result<Option<(Vec<u8>, i64)>> {
+        let sql = format!(
+            r#"
+            SELECT content, version
+            FROM {metadata}
+            WHERE index = $1
+              AND path = $2
+            "#,
+            metadata = self.tables.metadata,
+        );
+
+        let query = sqlx::query_as::<_, (Vec<u8>, i64)>(&sql)
+            .bind(index)
+            .bind(path);
+
+        let Some((content, version)) = query.fetch_optional(&self.pool).await? else {
+            return Ok(None);
+        };
+
+        let content = compression::decompress(content).wrap_err("failed to decompress")?;
+
+        Ok(Some((content, version)))
+    }
+
+    async fn version(&self, index: Uuid, path: &str) -> Result<Option<i64>> {
+        let sql = format!(
+            r#"
+            SELECT version
+            FROM {metadata}
+            WHERE index = $1
+              AND path = $2
+            "#,
+            metadata = self.tables.metadata,
+        );
+
+        let query = sqlx::query_scalar::<_, i64>(&sql).bind(index).bind(path);
+
+        Ok(query.fetch_optional(&self.pool).await?)
+    }
+
+    async fn write(
+        &self,
+        index: Uuid,
+        path: &str,
+        content: &[u8],
+        epoch: Option<i64>,
+        expected: Expected,
+    ) -> Result<i64, WriteError> {
+        let mut tx = self.pool.begin().await?;
+        let version = self
+            .write_in(&mut tx, index, path, content, epoch, expected)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(version)
+    }
+
+    async fn commit(
+        &self,
+        index: Uuid,
+        files: &[(String, FileMetadata)],
+        path: &str,
+        content: &[u8],
+        epoch: Option<i64>,
+        expected: Expected,
+    ) -> Result<i64, WriteError> {
+        // The files are only registered if the write succeeds, and the write is only
+        // visible once the files have been registered.
+        let mut tx = self.pool.begin().await?;
+        self.register_in(&mut tx, index, files).await?;
+
+        let version = self
+            .write_in(&mut tx, index, path, content, epoch, expected)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(version)
+    }
+
+    async fn file(&self, index: Uuid, path: &str) -> Result<Option<RegisteredFile>> {
+        let sql = format!(
+            r#"
+            SELECT deleted, length, etag
+            FROM {files}
+            WHERE index = $1
+              AND path = $2
+            "#,
+            files = self.tables.files,
+        );
+
+        let query = sqlx::query_as::<_, RegisteredFile>(&sql)
+            .bind(index)
+            .bind(path);
+
+        Ok(query.fetch_optional(&self.pool).await?)
+    }
+
+    async fn register(&self, index: Uuid, files: &[(String, FileMetadata)]) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        self.register_in(&mut conn, index, files).await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, index: Uuid, path: &str) -> Result<()> {
+        let sql = format!(
+            r#"
+            INSERT INTO {files} AS existing
+              (index, path, deleted, deleted_at)
+            VALUES ($1, $2, TRUE, NOW())
+            ON CONFLICT (index, path)
+            DO UPDATE SET
+              deleted = TRUE,
+              deleted_at = COALESCE(existing.deleted_at, NOW())
+            "#,
+            files = self.tables.files,
+        );
+
+        let query = sqlx::query(&sql).bind(index).bind(path);
+        query.execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    /// Files which are listed in the `.managed.json` of a retained revision are never
+    /// returned, so that directories pinned at those revisions can keep reading them.
+    async fn deleted(&self, index: Uuid, grace: Duration, limit: i64) -> Result<Vec<DeletedFile>> {
+        let Tables {
+            files, revisions, ..
+        } = &*self.tables;
+
+        let sql = format!(
+            r#"
+            SELECT DISTINCT managed
+            FROM {revisions}
+            WHERE index = $1
+              AND managed IS NOT NULL
+            "#
+        );
+
+        // The content of `.managed.json` might be compressed, so it is parsed here
+        // rather than in the query.
+        let query = sqlx::query_scalar::<_, Vec<u8>>(&sql).bind(index);
+        let mut referenced = Vec::new();
+        for managed in query.fetch_all(&self.pool).await? {
+            let managed = compression::decompress(managed).wrap_err("failed to decompress")?;
+            let paths = serde_json::from_slice::<Vec<String>>(&managed)
+                .wrap_err("failed to parse managed files")?;
+
+            referenced.extend(paths);
+        }
+
+        let sql = format!(
+            r#"
+            SELECT path, length
+            FROM {files}
+            WHERE index = $1
+              AND deleted
+              AND deleted_at <= NOW() - make_interval(secs => $2)
+              AND NOT path = ANY($4)
+            ORDER BY deleted_at
+            LIMIT $3
+            "#
+        );
+
+        let query = sqlx::query_as::<_, DeletedFile>(&sql)
+            .bind(index)
+            .bind(grace.as_secs_f64())
+            .bind(limit)
+            .bind(referenced);
+
+        Ok(query.fetch_all(&self.pool).await?)
+    }
+
+    async fn purge(&self, index: Uuid, paths: &[String]) -> Result<()> {
+        let sql = format!(
+            r#"
+            DELETE
+            FROM {files}
+            WHERE index = $1
+              AND path = ANY($2)
+              AND deleted
+            "#,
+            files = self.tables.files,
+        );
+
+        let query = sqlx::query(&sql).bind(index).bind(paths);
+        query.execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn files(&self, index: Uuid) -> Result<Vec<String>> {
+        let sql = format!(
+            r#"
+            SELECT path
+            FROM {files}
+            WHERE index = $1
+            "#,
+            files = self.tables.files,
+        );
+
+        let query = sqlx::query_scalar::<_, String>(&sql).bind(index);
+
+        Ok(query.fetch_all(&self.pool).await?)
+    }
+
+    async fn registered(&self, index: Uuid, paths: &[String]) -> Result<Vec<String>> {
+        let sql = format!(
+            r#"
+            SELECT path
+            FROM {files}
+            WHERE index = $1
+              AND path = ANY($2)
+              AND NOT deleted
+            "#,
+            files = self.tables.files,
+        );
+
+        let query = sqlx::query_scalar::<_, String>(&sql)
+            .bind(index)
+            .bind(paths);
+
+        Ok(query.fetch_all(&self.pool).await?)
+    }
+
+    async fn fence(&self, index: Uuid) -> Result<i64> {
+        let sql = format!(
+            r#"
+            UPDATE {directories}
+            SET epoch = epoch + 1
+            WHERE index = $1
+            RETURNING epoch
+            "#,
+            directories = self.tables.directories,
+        );
+
+        let query = sqlx::query_scalar::<_, i64>(&sql).bind(index);
+
+        Ok(query.fetch_one(&self.pool).await?)
+    }
+
+    async fn revisions(&self, index: Uuid) -> Result<Vec<Revision>> {
+        let sql = format!(
+            r#"
+            SELECT
+              version,
+              (EXTRACT(EPOCH FROM created_at) * 1000000)::BIGINT
+            FROM {revisions}
+            WHERE index = $1
+            ORDER BY version DESC
+            "#,
+            revisions = self.tables.revisions,
+        );
+
+        let query = sqlx::query_as::<_, (i64, i64)>(&sql).bind(index);
+        let rows = query.fetch_all(&self.pool).await?;
+
+        let revisions = rows
+            .into_iter()
+            .map(|(version, created_at)| Revision {
+                version,
+                created_at: SystemTime::UNIX_EPOCH + Duration::from_micros(created_at as u64),
+            })
+            .collect();
+
+        Ok(revisions)
+    }
+
+    async fn read_revision(&self, index: Uuid, version: i64) -> Result<Option<RevisionContent>> {
+        let sql = format!(
+            r#"
+            SELECT content, managed
+            FROM {revisions}
+            WHERE index = $1
+              AND version = $2
+            "#,
+            revisions = self.tables.revisions,
+        );
+
+        let query = sqlx::query_as::<_, RevisionContent>(&sql)
+            .bind(index)
+            .bind(version);
+
+        let Some(revision) = query.fetch_optional(&self.pool).await? else {
+            return Ok(None);
+        };
+
+        let revision =
+            compression::decompress_revision(revision).wrap_err("failed to decompress")?;
+
+        Ok(Some(revision))
+    }
+
+    async fn stats(&self, index: Uuid) -> Result<Vec<CommitStats>> {
+        let sql = format!(
+            r#"
+            SELECT
+              version,
+              opstamp,
+              segments,
+              docs,
+              deleted_docs,
+              payload,
+              schema_hash,
+              (EXTRACT(EPOCH FROM created_at) * 1000000)::BIGINT
+            FROM {stats}
+            WHERE index = $1
+            ORDER BY version DESC
+            "#,
+            stats = self.tables.stats,
+        );
+
+        type Row = (i64, i64, i64, i64, i64, Option<String>, String, i64);
+        let query = sqlx::query_as::<_, Row>(&sql).bind(index);
+        let rows = query.fetch_all(&self.pool).await?;
+
+        let stats = rows
+            .into_iter()
+            .map(
+                |(
+                    version,
+                    opstamp,
+                    segments,
+                    docs,
+                    deleted_docs,
+                    payload,
+                    schema_hash,
+                    created_at,
+                )| {
+                    CommitStats {
+                        version,
+                        opstamp,
+                        segments,
+                        docs,
+                        deleted_docs,
+                        payload,
+                        schema_hash,
+                        created_at: SystemTime::UNIX_EPOCH
+                            + Duration::from_micros(created_at as u64),
+                    }
+                },
+            )
+            .collect();
+
+        Ok(stats)
+    }
+
+    async fn rollback(
+        &self,
+        index: Uuid,
+        version: i64,
+        paths: &[String],
+        restored: &[(String, FileMetadata)],
+        epoch: Option<i64>,
+    ) -> Result<Option<i64>> {
+        let Tables {
+            files, revisions, ..
+        } = &*self.tables;
+
+        let mut tx = self.pool.begin().await?;
+
+        // Locking the revision makes sure that it cannot be removed, and thus that the
+        // files it references cannot be removed either, until the rollback is done.
+        let sql = format!(
+            r#"
+            SELECT content, managed
+            FROM {revisions}
+            WHERE index = $1
+              AND version = $2
+            FOR SHARE
+            "#
+        );
+
+        let query = sqlx::query_as::<_, RevisionContent>(&sql)
+            .bind(index)
+            .bind(version);
+
+        let Some(revision) = query.fetch_optional(&mut *tx).await? else {
+            return Ok(None);
+        };
+
+        // The content is compressed again when written.
+        let revision =
+            compression::decompress_revision(revision).wrap_err("failed to decompress")?;
+
+        self.register_in(&mut tx, index, restored).await?;
+
+        // The files which have been committed since the revision are not referenced
+        // anymore, as if tantivy had removed them.
+        if revision.managed.is_some() {
+            let sql = format!(
+                r#"
+                UPDATE {files}
+                SET
+                  deleted = TRUE,
+                  deleted_at = NOW()
+                WHERE index = $1
+                  AND NOT deleted
+                  AND NOT path = ANY($2)
+                "#
+            );
+
+            let delete = sqlx::query(&sql).bind(index).bind(paths);
+            delete.execute(&mut *tx).await?;
+        }
+
+        // `.managed.json` is written first, so that it is recorded along with the new
+        // revision of `meta.json`.
+        if let Some(managed) = &revision.managed {
+            let path = MANAGED_JSON
+                .to_str()
+                .expect("`.managed.json` is valid UTF-8");
+            self.write_in(&mut tx, index, path, managed, epoch, Expected::Any)
+                .await?;
+        }
+
+        let path = META_JSON.to_str().expect("`meta.json` is valid UTF-8");
+        let version = self
+            .write_in(
+                &mut tx,
+                index,
+                path,
+                &revision.content,
+                epoch,
+                Expected::Any,
+            )
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(version))
+    }
+
+    fn postgres(&self) -> Option<&PostgresBackend> {
+        Some(self)
+    }
+}
@@ -0,0 +1,37 @@
+use std::{borrow::Cow, io};
+
+use crate::RevisionContent;
+
+/// The magic number at the start of every zstd frame, which is used to tell compressed
+/// content apart from content stored uncompressed, as JSON never starts with it.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// The zstd compression level used, which is zstd's default.
+const LEVEL: i32 = 0;
+
+/// Compresses the given content of a metadata file, if `enabled`.
+pub(crate) fn compress(content: &[u8], enabled: bool) -> io::Result<Cow<'_, [u8]>> {
+    if !enabled {
+        return Ok(Cow::Borrowed(content));
+    }
+
+    zstd::encode_all(content, LEVEL).map(Cow::Owned)
+}
+
+/// Decompresses the given content of a metadata file, which is returned as-is if it
+/// was stored uncompressed.
+pub(crate) fn decompress(content: Vec<u8>) -> io::Result<Vec<u8>> {
+    if content.starts_with(&ZSTD_MAGIC) {
+        zstd::decode_all(content.as_slice())
+    } else {
+        Ok(content)
+    }
+}
+
+/// Decompresses the content of the metadata files at a given revision.
+pub(crate) fn decompress_revision(revision: RevisionContent) -> io::Result<RevisionContent> {
+    Ok(RevisionContent {
+        content: decompress(revision.content)?,
+        managed: revision.managed.map(decompress).transpose()?,
+    })
+}
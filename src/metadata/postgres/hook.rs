@@ -0,0 +1,25 @@
+use std::fmt;
+
+use async_trait::async_trait;
+use eyre::Result;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// A hook called every time `meta.json` is written by a [`PostgresBackend`][1], inside
+/// the transaction writing it, so that application state can be committed atomically
+/// with the index.
+///
+/// This is also called when an index is rolled back, as it writes `meta.json`.
+///
+/// [1]: super::PostgresBackend
+#[async_trait]
+pub trait CommitHook: fmt::Debug + Send + Sync + 'static {
+    /// Called once `meta.json` has been written at the given version, using the
+    /// connection of the transaction writing it.
+    ///
+    /// Returning an error aborts the transaction, in which case the write fails with
+    /// [`WriteError::Other`][1].
+    ///
+    /// [1]: crate::WriteError::Other
+    async fn on_commit(&self, index: Uuid, version: i64, conn: &mut PgConnection) -> Result<()>;
+}
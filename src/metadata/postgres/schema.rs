@@ -0,0 +1,165 @@
+use derive_more::{Display, Error};
+use eyre::{Context, Result};
+use sqlx::{
+    PgConnection, PgPool,
+    migrate::{Migrate, Migrator},
+};
+
+use super::tables::Tables;
+
+/// The migrations of this crate, embedded at compile time.
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// The version of the schema this version of the crate requires, which is recorded in
+/// the `schema_version` table by the migrations.
+///
+/// This must be bumped along with every new migration.
+pub(crate) const SCHEMA_VERSION: i64 = 9;
+
+/// An error returned when opening a directory whose metadata is stored in a database
+/// which was not migrated to the version of the schema required by this crate.
+#[derive(Debug, Display, Error)]
+pub enum SchemaError {
+    /// The database has not been migrated to the required version yet, which can be
+    /// done using [`migrate()`].
+    #[display("the database schema is at version {found}, but version {required} is required")]
+    TooOld {
+        /// The version of the schema of the database, or `0` if it was never migrated.
+        found: i64,
+
+        /// The version of the schema required by this crate.
+        required: i64,
+    },
+
+    /// The database has been migrated by a more recent version of this crate.
+    #[display("the database schema is at version {found}, which is newer than version {required}")]
+    TooNew {
+        /// The version of the schema of the database.
+        found: i64,
+
+        /// The version of the schema required by this crate.
+        required: i64,
+    },
+}
+
+/// Applies the migrations of this crate which have not been applied yet, creating the
+/// tables in the `tantivy` schema.
+///
+/// The applied migrations are recorded by [`sqlx`] in the `_sqlx_migrations` table.
+/// Databases which were migrated by hand, and thus have no migration recorded, are
+/// baselined using the version recorded in `tantivy.schema_version`: the migrations up
+/// to that version are recorded as applied without being run. Databases migrated by
+/// hand before version 8, which created that table, are baselined at version 1 if the
+/// `tantivy` schema exists, and the following migrations are then applied.
+///
+/// Only the default `tantivy` schema is supported: tables in another schema or with
+/// a prefix (see [`PostgresBackend::with_schema()`][1]) have to be created and migrated
+/// by hand.
+///
+/// [1]: crate::PostgresBackend::with_schema
+pub async fn migrate(pool: &PgPool) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+
+    // The lock prevents concurrent calls from baselining or migrating the database at
+    // the same time, and is released before migrating, which acquires it again.
+    conn.lock().await?;
+    let baseline = baseline(&mut conn).await;
+    conn.unlock().await?;
+    baseline.wrap_err("failed to baseline migrations")?;
+
+    MIGRATOR
+        .run(pool)
+        .await
+        .wrap_err("failed to apply migrations")
+}
+
+/// Records the migrations up to the version of the schema of the database as applied,
+/// if it was migrated by hand.
+async fn baseline(conn: &mut PgConnection) -> Result<()> {
+    conn.ensure_migrations_table().await?;
+    if !conn.list_applied_migrations().await?.is_empty() {
+        return Ok(());
+    }
+
+    let tables = Tables::default();
+    let mut found = version(&mut *conn, &tables).await?;
+    if found == 0 {
+        let sql = r#"
+            SELECT EXISTS (
+              SELECT
+              FROM information_schema.schemata
+              WHERE schema_name = $1
+            )
+        "#;
+
+        // The schema is created by the first migration, so the database was at least
+        // migrated to version 1 if it exists, and the following migrations are
+        // applied by `migrate()` on top of it.
+        let query = sqlx::query_scalar::<_, bool>(sql).bind(&tables.schema);
+        if !query.fetch_one(&mut *conn).await? {
+            return Ok(());
+        }
+
+        found = 1;
+    }
+
+    let sql = r#"
+        INSERT INTO _sqlx_migrations
+          (version, description, success, checksum, execution_time)
+        VALUES ($1, $2, TRUE, $3, 0)
+        ON CONFLICT DO NOTHING
+    "#;
+
+    for migration in MIGRATOR.iter() {
+        if migration.version > found {
+            break;
+        }
+
+        let query = sqlx::query(sql)
+            .bind(migration.version)
+            .bind(&*migration.description)
+            .bind(&*migration.checksum);
+
+        query.execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Checks that the schema of the database storing the given tables is at the version
+/// required by this crate, returning a [`SchemaError`] otherwise.
+pub(crate) async fn check(pool: &PgPool, tables: &Tables) -> Result<()> {
+    let mut conn = pool.acquire().await?;
+    let found = version(&mut conn, tables).await?;
+
+    let required = SCHEMA_VERSION;
+    if found < required {
+        Err(SchemaError::TooOld { found, required }.into())
+    } else if found > required {
+        Err(SchemaError::TooNew { found, required }.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the version of the schema of the database storing the given tables, or `0`
+/// if it was never migrated.
+async fn version(conn: &mut PgConnection, tables: &Tables) -> Result<i64> {
+    let sql = format!(
+        r#"
+        SELECT MAX(version)
+        FROM {schema_version}
+        "#,
+        schema_version = tables.schema_version,
+    );
+
+    let query = sqlx::query_scalar::<_, Option<i64>>(&sql);
+    match query.fetch_one(conn).await {
+        Ok(found) => Ok(found.unwrap_or(0)),
+
+        // The table is missing if the database was never migrated, or was migrated
+        // before it was created.
+        Err(sqlx::Error::Database(error)) if error.code().as_deref() == Some("42P01") => Ok(0),
+        Err(error) => Err(error).wrap_err("failed to check schema version"),
+    }
+}
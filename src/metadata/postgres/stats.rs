@@ -0,0 +1,92 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+/// The parts of `meta.json` from which the statistics of a commit are computed.
+#[derive(Deserialize)]
+struct Meta {
+    segments: Vec<SegmentMeta>,
+    schema: Value,
+    opstamp: u64,
+    payload: Option<String>,
+}
+
+/// The parts of the metadata of a segment from which the statistics of a commit are
+/// computed.
+#[derive(Deserialize)]
+struct SegmentMeta {
+    max_doc: u32,
+    deletes: Option<DeleteMeta>,
+}
+
+/// The metadata of the deletes of a segment.
+#[derive(Deserialize)]
+struct DeleteMeta {
+    num_deleted_docs: u32,
+}
+
+/// The statistics of a commit, before they are stored.
+pub(crate) struct Stats {
+    pub opstamp: i64,
+    pub segments: i64,
+    pub docs: i64,
+    pub deleted_docs: i64,
+    pub payload: Option<String>,
+
+    /// The schema serialized with its keys sorted, which is hashed when stored.
+    pub schema: String,
+}
+
+impl Stats {
+    /// Computes the statistics of a commit from the content of the `meta.json` it
+    /// wrote, returning `None` if it cannot be parsed or if a segment has more deleted
+    /// documents than documents.
+    pub fn parse(content: &[u8]) -> Option<Self> {
+        let meta = serde_json::from_slice::<Meta>(content).ok()?;
+
+        let mut docs = 0;
+        let mut deleted_docs = 0;
+        for segment in &meta.segments {
+            let deleted = segment
+                .deletes
+                .as_ref()
+                .map_or(0, |deletes| deletes.num_deleted_docs);
+
+            docs += i64::from(segment.max_doc.checked_sub(deleted)?);
+            deleted_docs += i64::from(deleted);
+        }
+
+        Some(Self {
+            opstamp: meta.opstamp as i64,
+            segments: meta.segments.len() as i64,
+            docs,
+            deleted_docs,
+            payload: meta.payload,
+            schema: canonical(&meta.schema),
+        })
+    }
+}
+
+/// Serializes the given value with the keys of its objects sorted, so that the result
+/// does not depend on whether `serde_json` preserves the order of keys or not.
+fn canonical(value: &Value) -> String {
+    match value {
+        Value::Array(values) => {
+            let values = values.iter().map(canonical).collect::<Vec<_>>();
+            format!("[{}]", values.join(","))
+        }
+
+        Value::Object(object) => {
+            let mut entries = object.iter().collect::<Vec<_>>();
+            entries.sort_unstable_by_key(|(key, _)| *key);
+
+            let entries = entries
+                .into_iter()
+                .map(|(key, value)| format!("{}:{}", Value::from(key.as_str()), canonical(value)))
+                .collect::<Vec<_>>();
+
+            format!("{{{}}}", entries.join(","))
+        }
+
+        value => value.to_string(),
+    }
+}
@@ -0,0 +1,51 @@
+/// The schema in which the tables are created by the migrations of this crate.
+const SCHEMA: &str = "tantivy";
+
+/// The quoted and schema-qualified names of the tables used to store the metadata in
+/// PostgreSQL, ready to be interpolated into queries.
+#[derive(Debug)]
+pub(crate) struct Tables {
+    /// The schema containing the tables.
+    pub schema: String,
+
+    /// The prefix of the names of the tables.
+    pub prefix: String,
+
+    pub directories: String,
+    pub metadata: String,
+    pub files: String,
+    pub revisions: String,
+    pub locks: String,
+    pub stats: String,
+    pub schema_version: String,
+}
+
+impl Tables {
+    /// Returns the names of the tables in the given schema, with the given prefix.
+    pub fn new(schema: &str, prefix: &str) -> Self {
+        let table = |name: &str| format!("{}.{}", quote(schema), quote(&format!("{prefix}{name}")));
+
+        Self {
+            schema: schema.to_owned(),
+            prefix: prefix.to_owned(),
+            directories: table("directories"),
+            metadata: table("metadata"),
+            files: table("files"),
+            revisions: table("revisions"),
+            locks: table("locks"),
+            stats: table("stats"),
+            schema_version: table("schema_version"),
+        }
+    }
+}
+
+impl Default for Tables {
+    fn default() -> Self {
+        Self::new(SCHEMA, "")
+    }
+}
+
+/// Quotes the given identifier, so that it can be safely interpolated into a query.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
@@ -0,0 +1,298 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use eyre::{Context, Result};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use super::{DeletedFile, Expected, MetadataBackend, RegisteredFile, WriteError};
+use crate::FileMetadata;
+
+/// A [`MetadataBackend`] storing the metadata in SQLite, meant for small deployments and
+/// local development.
+///
+/// The tables are created when the backend is created, if they do not exist yet. This
+/// backend does not support locking, watching, nor retaining revisions of `meta.json`.
+#[derive(Clone, Debug)]
+pub struct SqliteBackend {
+    /// Pool of connections to interact with SQLite.
+    pool: SqlitePool,
+}
+
+impl SqliteBackend {
+    /// Creates a new backend storing the metadata using the given pool of connections,
+    /// creating the tables it uses if they do not exist yet.
+    pub async fn new(pool: SqlitePool) -> Result<Self> {
+        let statements = [
+            r#"
+            CREATE TABLE IF NOT EXISTS tantivy_directories (
+              "index" BLOB NOT NULL PRIMARY KEY,
+              epoch INTEGER NOT NULL DEFAULT 0
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS tantivy_metadata (
+              "index" BLOB NOT NULL REFERENCES tantivy_directories ON DELETE CASCADE,
+              path TEXT NOT NULL,
+              content BLOB NOT NULL,
+              version INTEGER NOT NULL DEFAULT 1,
+              PRIMARY KEY ("index", path)
+            )
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS tantivy_files (
+              "index" BLOB NOT NULL REFERENCES tantivy_directories ON DELETE CASCADE,
+              path TEXT NOT NULL,
+              deleted BOOLEAN NOT NULL DEFAULT FALSE,
+              deleted_at REAL,
+              length INTEGER,
+              etag TEXT,
+              PRIMARY KEY ("index", path)
+            )
+            "#,
+        ];
+
+        for statement in statements {
+            sqlx::query(statement)
+                .execute(&pool)
+                .await
+                .wrap_err("failed to create tables")?;
+        }
+
+        Ok(Self { pool })
+    }
+
+    /// Returns the pool of connections used to interact with SQLite.
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+}
+
+#[async_trait]
+impl MetadataBackend for SqliteBackend {
+    async fn open(&self, index: Uuid) -> Result<()> {
+        let create = sqlx::query(
+            r#"
+            INSERT INTO tantivy_directories ("index")
+            VALUES (?1)
+            ON CONFLICT DO NOTHING
+            "#,
+        );
+
+        create
+            .bind(index)
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to create index")?;
+
+        Ok(())
+    }
+
+    async fn read(&self, index: Uuid, path: &str) -> Result<Option<(Vec<u8>, i64)>> {
+        let query = sqlx::query_as(
+            r#"
+            SELECT content, version
+            FROM tantivy_metadata
+            WHERE "index" = ?1
+              AND path = ?2
+            "#,
+        );
+
+        let row = query.bind(index).bind(path).fetch_optional(&self.pool);
+        Ok(row.await?)
+    }
+
+    async fn write(
+        &self,
+        index: Uuid,
+        path: &str,
+        content: &[u8],
+        epoch: Option<i64>,
+        expected: Expected,
+    ) -> Result<i64, WriteError> {
+        let (check, expected) = match expected {
+            Expected::Any => (false, None),
+            Expected::Missing => (true, None),
+            Expected::Version(version) => (true, Some(version)),
+        };
+
+        // A single statement is atomic, so the epoch cannot be incremented between the
+        // check and the write.
+        let query = sqlx::query_scalar(
+            r#"
+            INSERT INTO tantivy_metadata
+              ("index", path, content)
+            SELECT "index", ?2, ?3
+            FROM tantivy_directories
+            WHERE "index" = ?1
+              AND (?4 IS NULL OR epoch <= ?4)
+            ON CONFLICT ("index", path)
+            DO UPDATE SET
+              content = excluded.content,
+              version = tantivy_metadata.version + 1
+            WHERE NOT ?5
+               OR tantivy_metadata.version = ?6
+            RETURNING version
+            "#,
+        );
+
+        let query = query
+            .bind(index)
+            .bind(path)
+            .bind(content)
+            .bind(epoch)
+            .bind(check)
+            .bind(expected);
+
+        let Some(version) = query.fetch_optional(&self.pool).await? else {
+            let query = sqlx::query_scalar::<_, i64>(
+                r#"
+                SELECT epoch
+                FROM tantivy_directories
+                WHERE "index" = ?1
+                "#,
+            );
+
+            let current = query.bind(index).fetch_one(&self.pool).await?;
+            return match epoch {
+                Some(epoch) if epoch < current => Err(WriteError::Fenced { epoch }),
+                _ => Err(WriteError::Conflict { expected }),
+            };
+        };
+
+        Ok(version)
+    }
+
+    async fn file(&self, index: Uuid, path: &str) -> Result<Option<RegisteredFile>> {
+        let query = sqlx::query_as(
+            r#"
+            SELECT deleted, length, etag
+            FROM tantivy_files
+            WHERE "index" = ?1
+              AND path = ?2
+            "#,
+        );
+
+        let row = query
+            .bind(index)
+            .bind(path)
+            .fetch_optional(&self.pool)
+            .await?;
+        let file = row.map(|(deleted, length, etag)| RegisteredFile {
+            deleted,
+            length,
+            etag,
+        });
+
+        Ok(file)
+    }
+
+    async fn register(&self, index: Uuid, files: &[(String, FileMetadata)]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for (path, metadata) in files {
+            let query = sqlx::query(
+                r#"
+                INSERT INTO tantivy_files
+                  ("index", path, length, etag)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT ("index", path)
+                DO UPDATE SET
+                  deleted = FALSE,
+                  deleted_at = NULL,
+                  length = excluded.length,
+                  etag = excluded.etag
+                "#,
+            );
+
+            query
+                .bind(index)
+                .bind(path)
+                .bind(metadata.length as i64)
+                .bind(&metadata.etag)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, index: Uuid, path: &str) -> Result<()> {
+        let query = sqlx::query(
+            r#"
+            INSERT INTO tantivy_files
+              ("index", path, deleted, deleted_at)
+            VALUES (?1, ?2, TRUE, unixepoch('subsec'))
+            ON CONFLICT ("index", path)
+            DO UPDATE SET
+              deleted = TRUE,
+              deleted_at = COALESCE(tantivy_files.deleted_at, unixepoch('subsec'))
+            "#,
+        );
+
+        query.bind(index).bind(path).execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn deleted(&self, index: Uuid, grace: Duration, limit: i64) -> Result<Vec<DeletedFile>> {
+        let query = sqlx::query_as(
+            r#"
+            SELECT path, length
+            FROM tantivy_files
+            WHERE "index" = ?1
+              AND deleted
+              AND deleted_at <= unixepoch('subsec') - ?2
+            ORDER BY deleted_at
+            LIMIT ?3
+            "#,
+        );
+
+        let query = query.bind(index).bind(grace.as_secs_f64()).bind(limit);
+        Ok(query.fetch_all(&self.pool).await?)
+    }
+
+    async fn purge(&self, index: Uuid, paths: &[String]) -> Result<()> {
+        // SQLite does not support binding arrays, so the paths are passed as JSON.
+        let paths = serde_json::to_string(paths)?;
+        let query = sqlx::query(
+            r#"
+            DELETE
+            FROM tantivy_files
+            WHERE "index" = ?1
+              AND path IN (SELECT value FROM json_each(?2))
+              AND deleted
+            "#,
+        );
+
+        query.bind(index).bind(paths).execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    async fn files(&self, index: Uuid) -> Result<Vec<String>> {
+        let query = sqlx::query_scalar(
+            r#"
+            SELECT path
+            FROM tantivy_files
+            WHERE "index" = ?1
+            "#,
+        );
+
+        Ok(query.bind(index).fetch_all(&self.pool).await?)
+    }
+
+    async fn fence(&self, index: Uuid) -> Result<i64> {
+        let query = sqlx::query_scalar(
+            r#"
+            UPDATE tantivy_directories
+            SET epoch = epoch + 1
+            WHERE "index" = ?1
+            RETURNING epoch
+            "#,
+        );
+
+        Ok(query.bind(index).fetch_one(&self.pool).await?)
+    }
+}
@@ -6,9 +6,11 @@ use std::{
 };
 
 use derive_more::{Deref, From};
-use opendal::{ErrorKind, Metadata};
+use opendal::ErrorKind;
 use tantivy::directory::error::OpenReadError;
 
+use crate::file::FileMetadata;
+
 #[derive(Clone, Deref, From)]
 pub(crate) struct Operator {
     #[deref]
@@ -19,7 +21,7 @@ impl Operator {
     /// Fetches the metadata for the file at the given path.
     ///
     /// Fails if the path does not exist, or if it is not pointing to a file.
-    pub async fn metadata(&self, filepath: &Path) -> Result<Metadata, OpenReadError> {
+    pub async fn metadata(&self, filepath: &Path) -> Result<FileMetadata, OpenReadError> {
         let Some(path) = filepath.to_str() else {
             let filepath = filepath.to_path_buf();
             return Err(OpenReadError::FileDoesNotExist(filepath));
@@ -28,7 +30,7 @@ impl Operator {
         match self.operator.stat(path).await {
             Ok(metadata) => {
                 if metadata.is_file() {
-                    Ok(metadata)
+                    Ok(FileMetadata::from(metadata))
                 } else {
                     let filepath = filepath.to_path_buf();
                     Err(OpenReadError::FileDoesNotExist(filepath))
@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use eyre::{Result, bail};
+
+use crate::{Locking, Watching};
+
+/// Options used to configure a [`RemoteDirectory`][1].
+///
+/// [1]: crate::RemoteDirectory
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Configures the caching of opened files and of their metadata.
+    pub cache: CacheOptions,
+
+    /// Configures how locks are acquired.
+    pub locking: Locking,
+
+    /// Configures how changes to `meta.json` are watched for.
+    pub watching: Watching,
+
+    /// Configures how many revisions of `meta.json` are retained, when the metadata is
+    /// stored in PostgreSQL.
+    pub history: HistoryOptions,
+}
+
+impl Options {
+    /// Fails if the options are invalid.
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Locking::Lease { ttl } = self.locking
+            && (ttl / 3).is_zero()
+        {
+            bail!("the TTL of leases is too short for them to be refreshed");
+        }
+
+        if let Watching::Poll { interval } = self.watching
+            && interval.is_zero()
+        {
+            bail!("the interval at which changes are polled for must not be zero");
+        }
+
+        Ok(())
+    }
+}
+
+/// Configures the caching of opened files and of their metadata.
+#[derive(Clone, Debug)]
+pub struct CacheOptions {
+    /// The maximum number of entries in each cache, beyond which the least recently
+    /// used ones are evicted.
+    ///
+    /// Defaults to 10 000.
+    pub capacity: usize,
+
+    /// How long entries are cached for, or `None` to cache them until they are
+    /// evicted.
+    ///
+    /// Defaults to an hour.
+    pub ttl: Option<Duration>,
+
+    /// How long the fact that a file does not exist is cached for, or `None` to not
+    /// cache it.
+    ///
+    /// Defaults to 5 seconds.
+    pub negative_ttl: Option<Duration>,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            ttl: Some(Duration::from_secs(60 * 60)),
+            negative_ttl: Some(Duration::from_secs(5)),
+        }
+    }
+}
+
+/// Configures how many revisions of `meta.json` are retained.
+///
+/// A revision is removed as soon as it is either not one of the `retain` most recent
+/// ones or older than `max_age`. The current revision is always retained.
+///
+/// The statistics of commits (see [`CommitStats`][1]) are removed along with their
+/// revision, so retaining them for longer requires retaining more revisions.
+///
+/// [1]: crate::CommitStats
+#[derive(Clone, Debug)]
+pub struct HistoryOptions {
+    /// How many of the most recent revisions are retained, or `None` to not limit
+    /// their number.
+    ///
+    /// Defaults to 10.
+    pub retain: Option<usize>,
+
+    /// How long revisions are retained for, or `None` to retain them regardless of
+    /// their age.
+    ///
+    /// Defaults to `None`.
+    pub max_age: Option<Duration>,
+}
+
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        Self {
+            retain: Some(10),
+            max_age: None,
+        }
+    }
+}
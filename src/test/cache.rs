@@ -0,0 +1,96 @@
+use std::{path::Path, time::Duration};
+
+use tantivy::directory::error::OpenReadError;
+
+use crate::{CacheOptions, cache::Cache, file::FileMetadata};
+
+#[tokio::test]
+async fn negative_caching() {
+    let options = CacheOptions {
+        capacity: 10,
+        ttl: None,
+        negative_ttl: Some(Duration::from_secs(3600)),
+    };
+
+    let cache = Cache::new(&options);
+    let path = Path::new("missing");
+
+    let mut fetches = 0;
+    for _ in 0..2 {
+        let fetch = async || {
+            fetches += 1;
+            Err(OpenReadError::FileDoesNotExist(path.to_path_buf()))
+        };
+
+        let result = cache.metadata(path, fetch).await;
+        assert!(matches!(result, Err(OpenReadError::FileDoesNotExist(_))));
+    }
+
+    assert_eq!(fetches, 1);
+
+    // Creating the file invalidates the cached non-existence.
+    cache.invalidate(path);
+
+    let fetch = async || {
+        fetches += 1;
+        Ok(FileMetadata {
+            length: 42,
+            etag: None,
+        })
+    };
+
+    let metadata = cache
+        .metadata(path, fetch)
+        .await
+        .expect("failed to fetch metadata");
+
+    assert_eq!(metadata.length, 42);
+    assert_eq!(fetches, 2);
+}
+
+#[tokio::test]
+async fn eviction() {
+    let options = CacheOptions {
+        capacity: 10,
+        ttl: None,
+        negative_ttl: None,
+    };
+
+    let cache = Cache::new(&options);
+
+    let mut fetches = 0;
+    for length in 0..20 {
+        let path = length.to_string();
+        let fetch = async || {
+            fetches += 1;
+            Ok(FileMetadata { length, etag: None })
+        };
+
+        cache
+            .metadata(Path::new(&path), fetch)
+            .await
+            .expect("failed to fetch metadata");
+    }
+
+    assert_eq!(fetches, 20);
+
+    // The least recently used entries have been evicted, while the most recently used
+    // ones are still cached.
+    for (path, evicted) in [("0", true), ("19", false)] {
+        let mut fetched = false;
+        let fetch = async || {
+            fetched = true;
+            Ok(FileMetadata {
+                length: 0,
+                etag: None,
+            })
+        };
+
+        cache
+            .metadata(Path::new(path), fetch)
+            .await
+            .expect("failed to fetch metadata");
+
+        assert_eq!(fetched, evicted);
+    }
+}
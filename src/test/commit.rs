@@ -0,0 +1,59 @@
+use std::{path::Path, time::Duration};
+
+use tantivy::Directory;
+use tokio::{task, time};
+use uuid::uuid;
+
+use super::operator;
+use crate::{Commit, MemoryBackend, Options, RemoteDirectory};
+
+#[tokio::test]
+async fn wait_for_commit() {
+    let id = uuid!("7a3b9c1d-2e4f-4a5b-8c6d-0e1f2a3b4c5d");
+    let open = RemoteDirectory::open_with_backend(
+        id,
+        operator(),
+        MemoryBackend::new(),
+        Options::default(),
+    );
+
+    let directory = open.await.expect("failed to open directory");
+
+    let version = directory.version().await.expect("failed to get version");
+    assert_eq!(version, None);
+
+    let writer = directory.clone();
+    let commit = task::spawn(async move {
+        time::sleep(Duration::from_millis(100)).await;
+
+        task::spawn_blocking(move || {
+            writer
+                .atomic_write(Path::new("meta.json"), br#"{"opstamp":3}"#)
+                .expect("failed to write meta.json");
+        })
+        .await
+        .expect("failed to commit");
+    });
+
+    let timeout = Duration::from_secs(5);
+    let reached = directory
+        .wait_for_commit(Commit::Opstamp(3), timeout)
+        .await
+        .expect("failed to wait for commit");
+
+    assert!(reached);
+    commit.await.expect("failed to commit");
+
+    let version = directory.version().await.expect("failed to get version");
+    assert_eq!(version, Some(1));
+
+    let timeout = Duration::from_millis(100);
+    for commit in [Commit::Opstamp(4), Commit::Version(2)] {
+        let reached = directory
+            .wait_for_commit(commit, timeout)
+            .await
+            .expect("failed to wait for commit");
+
+        assert!(!reached);
+    }
+}
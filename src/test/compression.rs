@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use tantivy::Directory;
+use tokio::task;
+use uuid::uuid;
+
+use super::{operator, pool};
+use crate::{Options, PostgresBackend, RemoteDirectory};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn compression() {
+    let id = uuid!("8e0b2d4f-6a8c-4e1b-9d3f-5a7c9e1b3d5f");
+    let pool = pool(id).await;
+
+    let directory = RemoteDirectory::open(id, operator(), pool.clone())
+        .await
+        .expect("failed to open directory");
+
+    let meta = br#"{"segments":[],"opstamp":0}"#;
+    let directory_ = directory.clone();
+    let write = task::spawn_blocking(move || {
+        directory_
+            .atomic_write(Path::new("meta.json"), meta)
+            .expect("failed to write meta.json");
+    });
+
+    write.await.expect("failed to write");
+
+    let query = sqlx::query_scalar!(
+        r#"
+        SELECT content
+        FROM tantivy.metadata
+        WHERE index = $1
+          AND path = 'meta.json'
+        "#,
+        id,
+    );
+
+    let stored = query
+        .fetch_one(&pool)
+        .await
+        .expect("failed to read content");
+    assert_ne!(stored, meta);
+
+    // Files stored uncompressed, such as the ones written before compression was
+    // enabled, can still be read.
+    let backend = PostgresBackend::new(pool.clone()).with_compression(false);
+    let uncompressed =
+        RemoteDirectory::open_with_backend(id, operator(), backend, Options::default())
+            .await
+            .expect("failed to open directory");
+
+    let managed = br#"["segment.idx"]"#;
+    let write = task::spawn_blocking(move || {
+        uncompressed
+            .atomic_write(Path::new(".managed.json"), managed)
+            .expect("failed to write .managed.json");
+    });
+
+    write.await.expect("failed to write");
+
+    let query = sqlx::query_scalar!(
+        r#"
+        SELECT content
+        FROM tantivy.metadata
+        WHERE index = $1
+          AND path = '.managed.json'
+        "#,
+        id,
+    );
+
+    let stored = query
+        .fetch_one(&pool)
+        .await
+        .expect("failed to read content");
+    assert_eq!(stored, managed);
+
+    let read = task::spawn_blocking(move || {
+        let meta = directory
+            .atomic_read(Path::new("meta.json"))
+            .expect("failed to read meta.json");
+
+        let managed = directory
+            .atomic_read(Path::new(".managed.json"))
+            .expect("failed to read .managed.json");
+
+        (meta, managed)
+    });
+
+    let (read_meta, read_managed) = read.await.expect("failed to read");
+    assert_eq!(read_meta, meta);
+    assert_eq!(read_managed, managed);
+}
@@ -0,0 +1,163 @@
+use std::sync::Arc;
+
+use opendal::{
+    Buffer, Error, ErrorKind, Metadata, Result,
+    raw::{
+        Access, Layer, LayeredAccess, OpList, OpRead, OpStat, OpWrite, RpDelete, RpList, RpRead,
+        RpStat, RpWrite, oio,
+    },
+};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// A layer emulating conditional writes and entity tags, for services which do not
+/// support them (e.g. `Memory` and `Fs`).
+///
+/// Entity tags are hashes of the content of the objects, and conditional writes are
+/// serialized using a single lock, which is also held while computing entity tags.
+#[derive(Clone, Debug, Default)]
+pub struct ConditionalLayer {
+    /// The lock serializing conditional writes.
+    lock: Arc<Mutex<()>>,
+}
+
+/// The accessor returned by [`ConditionalLayer`].
+#[derive(Debug)]
+pub struct ConditionalAccessor<A> {
+    /// The wrapped accessor.
+    inner: A,
+
+    /// The lock serializing conditional writes.
+    lock: Arc<Mutex<()>>,
+}
+
+/// The writer returned by [`ConditionalAccessor`], holding the lock until the write is
+/// done if it is conditional.
+pub struct ConditionalWriter<W> {
+    /// The wrapped writer.
+    inner: W,
+
+    /// The guard of the lock, if the write is conditional.
+    _guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl<A: Access> Layer<A> for ConditionalLayer {
+    type LayeredAccess = ConditionalAccessor<A>;
+
+    fn layer(&self, inner: A) -> Self::LayeredAccess {
+        inner.info().update_full_capability(|mut capability| {
+            capability.write_with_if_match = true;
+            capability.write_with_if_not_exists = true;
+            capability
+        });
+
+        ConditionalAccessor {
+            inner,
+            lock: self.lock.clone(),
+        }
+    }
+}
+
+impl<A: Access> ConditionalAccessor<A> {
+    /// Returns the entity tag of the object at the given path, which must be called
+    /// while holding the lock.
+    ///
+    /// Returns `None` if the object does not exist.
+    async fn etag(&self, path: &str) -> Result<Option<String>> {
+        let (_, mut reader) = match self.inner.read(path, OpRead::new()).await {
+            Ok(read) => read,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        let content = oio::Read::read_all(&mut reader).await?;
+        let etag = gxhash::gxhash64(&content.to_bytes(), 0);
+
+        Ok(Some(format!("\"{etag:016x}\"")))
+    }
+}
+
+impl<A: Access> LayeredAccess for ConditionalAccessor<A> {
+    type Inner = A;
+    type Reader = A::Reader;
+    type Writer = ConditionalWriter<A::Writer>;
+    type Lister = A::Lister;
+    type Deleter = A::Deleter;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.inner
+    }
+
+    async fn read(&self, path: &str, args: OpRead) -> Result<(RpRead, Self::Reader)> {
+        self.inner.read(path, args).await
+    }
+
+    async fn write(&self, path: &str, args: OpWrite) -> Result<(RpWrite, Self::Writer)> {
+        if args.if_match().is_none() && !args.if_not_exists() {
+            let (write, inner) = self.inner.write(path, args).await?;
+            let writer = ConditionalWriter {
+                inner,
+                _guard: None,
+            };
+
+            return Ok((write, writer));
+        }
+
+        let guard = self.lock.clone().lock_owned().await;
+        let etag = self.etag(path).await?;
+        let matches = match args.if_match() {
+            Some(expected) => etag.as_deref() == Some(expected),
+            None => etag.is_none(),
+        };
+
+        if !matches {
+            let message = "the condition of the write did not hold";
+            return Err(Error::new(ErrorKind::ConditionNotMatch, message));
+        }
+
+        // The inner accessor does not support any conditions.
+        let (write, inner) = self.inner.write(path, OpWrite::new()).await?;
+        let writer = ConditionalWriter {
+            inner,
+            _guard: Some(guard),
+        };
+
+        Ok((write, writer))
+    }
+
+    async fn stat(&self, path: &str, args: OpStat) -> Result<RpStat> {
+        let _guard = self.lock.lock().await;
+        let metadata = self.inner.stat(path, args).await?.into_metadata();
+        if !metadata.mode().is_file() {
+            return Ok(RpStat::new(metadata));
+        }
+
+        let metadata = match self.etag(path).await? {
+            Some(etag) => metadata.with_etag(etag),
+            None => return Err(Error::new(ErrorKind::NotFound, "the object was deleted")),
+        };
+
+        Ok(RpStat::new(metadata))
+    }
+
+    async fn delete(&self) -> Result<(RpDelete, Self::Deleter)> {
+        self.inner.delete().await
+    }
+
+    async fn list(&self, path: &str, args: OpList) -> Result<(RpList, Self::Lister)> {
+        self.inner.list(path, args).await
+    }
+}
+
+impl<W: oio::Write> oio::Write for ConditionalWriter<W> {
+    async fn write(&mut self, bs: Buffer) -> Result<()> {
+        self.inner.write(bs).await
+    }
+
+    async fn close(&mut self) -> Result<Metadata> {
+        self.inner.close().await
+    }
+
+    async fn abort(&mut self) -> Result<()> {
+        self.inner.abort().await
+    }
+}
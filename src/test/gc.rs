@@ -0,0 +1,149 @@
+use std::{io::Write, path::Path, time::Duration};
+
+use tantivy::{Directory, directory::TerminatingWrite};
+use tokio::task;
+use uuid::uuid;
+
+use super::operator;
+use crate::{GarbageCollection, MemoryBackend, Options, Orphan, RemoteDirectory};
+
+#[tokio::test]
+async fn collect_garbage() {
+    let id = uuid!("0b9d1fb0-4cc8-4f4b-9c55-21cd4d7d1a0e");
+    let open = RemoteDirectory::open_with_backend(
+        id,
+        operator(),
+        MemoryBackend::new(),
+        Options::default(),
+    );
+
+    let directory = open.await.expect("failed to open directory");
+
+    let directory_ = directory.clone();
+    let write = task::spawn_blocking(move || {
+        for (name, content) in [
+            ("segment.idx", &b"segment"[..]),
+            ("aborted.idx", b"aborted!"),
+        ] {
+            let path = Path::new(name);
+            let mut writer = directory_.open_write(path).expect("failed to open writer");
+
+            writer.write_all(content).expect("failed to write");
+            writer.terminate().expect("failed to terminate writer");
+
+            directory_.sync_directory().expect("failed to sync");
+
+            // Committed files are registered along with their length, while files
+            // deleted before being committed are not.
+            if name == "segment.idx" {
+                directory_
+                    .atomic_write(Path::new("meta.json"), b"{}")
+                    .expect("failed to write meta.json");
+            }
+
+            directory_.delete(path).expect("failed to delete");
+        }
+    });
+
+    write.await.expect("failed to write and delete");
+
+    // The grace period has not expired yet.
+    let collection = directory
+        .collect_garbage(Duration::from_secs(3600))
+        .await
+        .expect("failed to collect garbage");
+
+    assert_eq!(collection, GarbageCollection::default());
+
+    let collection = directory
+        .collect_garbage(Duration::ZERO)
+        .await
+        .expect("failed to collect garbage");
+
+    let expected = GarbageCollection {
+        objects: 2,
+        bytes: 15,
+    };
+
+    assert_eq!(collection, expected);
+
+    let collection = directory
+        .collect_garbage(Duration::ZERO)
+        .await
+        .expect("failed to collect garbage");
+
+    assert_eq!(collection, GarbageCollection::default());
+}
+
+#[tokio::test]
+async fn remove_orphans() {
+    let id = uuid!("7a1f5a0c-3b8e-4d55-8f3e-5c4a2b0e9d11");
+    let operator = operator();
+    let open = RemoteDirectory::open_with_backend(
+        id,
+        operator.clone(),
+        MemoryBackend::new(),
+        Options::default(),
+    );
+
+    let directory = open.await.expect("failed to open directory");
+
+    let directory_ = directory.clone();
+    let write = task::spawn_blocking(move || {
+        let mut writer = directory_
+            .open_write(Path::new("segment.idx"))
+            .expect("failed to open writer");
+
+        writer.write_all(b"segment").expect("failed to write");
+        writer.terminate().expect("failed to terminate writer");
+
+        directory_.sync_directory().expect("failed to sync");
+    });
+
+    write.await.expect("failed to write");
+
+    // Written by a writer which crashed before committing.
+    let orphan = format!("idx-{id}/crashed.idx");
+    operator
+        .write(&orphan, b"crashed".to_vec())
+        .await
+        .expect("failed to write orphan");
+
+    let orphans = directory
+        .find_orphans(Duration::from_secs(3600))
+        .await
+        .expect("failed to find orphans");
+
+    assert!(orphans.is_empty());
+
+    let orphans = directory
+        .find_orphans(Duration::ZERO)
+        .await
+        .expect("failed to find orphans");
+
+    let paths = orphans
+        .iter()
+        .map(|Orphan { path, .. }| path.as_str())
+        .collect::<Vec<_>>();
+
+    assert_eq!(paths, [orphan.as_str()]);
+
+    let collection = directory
+        .remove_orphans(Duration::ZERO)
+        .await
+        .expect("failed to remove orphans");
+
+    let expected = GarbageCollection {
+        objects: 1,
+        bytes: 7,
+    };
+
+    assert_eq!(collection, expected);
+
+    let exists = operator
+        .exists(&orphan)
+        .await
+        .expect("failed to check orphan");
+
+    assert!(!exists);
+}
@@ -0,0 +1,324 @@
+use std::{io::Write, path::Path, time::Duration};
+
+use tantivy::{Directory, directory::TerminatingWrite};
+use tokio::task;
+use uuid::uuid;
+
+use super::{operator, pool};
+use crate::{HistoryOptions, Options, RemoteDirectory};
+
+#[tokio::test]
+async fn revisions() {
+    let id = uuid!("2d8e4f6a-1b3c-4d5e-8f9a-b0c1d2e3f4a5");
+    let pool = pool(id).await;
+
+    let options = Options {
+        history: HistoryOptions {
+            retain: Some(2),
+            max_age: None,
+        },
+        ..Default::default()
+    };
+
+    let directory = RemoteDirectory::open_with_options(id, operator(), pool, options)
+        .await
+        .expect("failed to open directory");
+
+    let writer = directory.clone();
+    let write = task::spawn_blocking(move || {
+        for opstamp in 1..=3 {
+            let meta = format!(r#"{{"opstamp":{opstamp}}}"#);
+            writer
+                .atomic_write(Path::new("meta.json"), meta.as_bytes())
+                .expect("failed to write meta.json");
+
+            // Only `meta.json` is versioned.
+            writer
+                .atomic_write(Path::new(".managed.json"), b"[]")
+                .expect("failed to write .managed.json");
+        }
+    });
+
+    write.await.expect("failed to write");
+
+    let revisions = directory
+        .revisions()
+        .await
+        .expect("failed to list revisions");
+
+    let versions = revisions
+        .iter()
+        .map(|revision| revision.version)
+        .collect::<Vec<_>>();
+
+    assert_eq!(versions, [3, 2]);
+    assert!(revisions[0].created_at >= revisions[1].created_at);
+
+    let pruned = directory
+        .read_revision(1)
+        .await
+        .expect("failed to read revision");
+
+    assert_eq!(pruned, None);
+
+    let retained = directory
+        .read_revision(2)
+        .await
+        .expect("failed to read revision");
+
+    assert_eq!(retained.as_deref(), Some(&br#"{"opstamp":2}"#[..]));
+}
+
+#[tokio::test]
+async fn open_at() {
+    let id = uuid!("8c1d3e5f-7a9b-4c2d-8e4f-6a8b0c2d4e6f");
+    let pool = pool(id).await;
+    let operator = operator();
+
+    let options = Options {
+        history: HistoryOptions {
+            retain: Some(2),
+            max_age: None,
+        },
+        ..Default::default()
+    };
+
+    let directory = RemoteDirectory::open_with_options(id, operator.clone(), pool.clone(), options)
+        .await
+        .expect("failed to open directory");
+
+    let writer = directory.clone();
+    let write = task::spawn_blocking(move || {
+        let mut file = writer
+            .open_write(Path::new("segment.idx"))
+            .expect("failed to open file");
+
+        file.write_all(b"segment").expect("failed to write file");
+        file.terminate().expect("failed to close file");
+
+        writer
+            .atomic_write(Path::new(".managed.json"), br#"["segment.idx"]"#)
+            .expect("failed to write .managed.json");
+
+        writer.sync_directory().expect("failed to sync directory");
+        writer
+            .atomic_write(Path::new("meta.json"), b"first")
+            .expect("failed to write meta.json");
+
+        // The file is deleted by the second commit.
+        writer
+            .atomic_write(Path::new(".managed.json"), b"[]")
+            .expect("failed to write .managed.json");
+
+        writer
+            .delete(Path::new("segment.idx"))
+            .expect("failed to delete file");
+
+        writer
+            .atomic_write(Path::new("meta.json"), b"second")
+            .expect("failed to write meta.json");
+    });
+
+    write.await.expect("failed to write");
+
+    // The file is still referenced by the first revision.
+    let collection = directory
+        .collect_garbage(Duration::ZERO)
+        .await
+        .expect("failed to collect garbage");
+
+    assert_eq!(collection.objects, 0);
+
+    let pinned = RemoteDirectory::open_at(id, 1, operator, pool)
+        .await
+        .expect("failed to open directory at first revision");
+
+    assert_eq!(pinned.pinned_at(), Some(1));
+
+    let read = task::spawn_blocking(move || {
+        let meta = pinned
+            .atomic_read(Path::new("meta.json"))
+            .expect("failed to read meta.json");
+
+        assert_eq!(meta, b"first");
+
+        let file = pinned
+            .open_read(Path::new("segment.idx"))
+            .expect("failed to open deleted file");
+
+        let content = file.read_bytes().expect("failed to read deleted file");
+        assert_eq!(content.as_slice(), b"segment");
+
+        let write = pinned.atomic_write(Path::new("meta.json"), b"third");
+        assert!(write.is_err());
+
+        let open = pinned.open_write(Path::new("other.idx"));
+        assert!(open.is_err());
+    });
+
+    read.await.expect("failed to read");
+
+    // Once the first revision is not retained anymore, the file can be removed.
+    let writer = directory.clone();
+    let write = task::spawn_blocking(move || {
+        writer
+            .atomic_write(Path::new("meta.json"), b"third")
+            .expect("failed to write meta.json");
+    });
+
+    write.await.expect("failed to write");
+
+    let collection = directory
+        .collect_garbage(Duration::ZERO)
+        .await
+        .expect("failed to collect garbage");
+
+    assert_eq!(collection.objects, 1);
+}
+
+#[tokio::test]
+async fn rollback() {
+    let id = uuid!("4f7a9c2e-6b8d-4e1f-a3c5-7d9e1f3a5b7c");
+    let pool = pool(id).await;
+    let operator = operator();
+
+    let directory = RemoteDirectory::open(id, operator.clone(), pool.clone())
+        .await
+        .expect("failed to open directory");
+
+    let writer = directory.clone();
+    let write = task::spawn_blocking(move || {
+        for name in ["first.idx", "second.idx"] {
+            let mut file = writer
+                .open_write(Path::new(name))
+                .expect("failed to open file");
+
+            file.write_all(b"segment").expect("failed to write file");
+            file.terminate().expect("failed to close file");
+        }
+
+        writer
+            .atomic_write(Path::new(".managed.json"), br#"["first.idx","second.idx"]"#)
+            .expect("failed to write .managed.json");
+
+        writer.sync_directory().expect("failed to sync directory");
+        writer
+            .atomic_write(Path::new("meta.json"), b"first")
+            .expect("failed to write meta.json");
+
+        writer
+            .atomic_write(Path::new(".managed.json"), b"[]")
+            .expect("failed to write .managed.json");
+
+        for name in ["first.idx", "second.idx"] {
+            writer
+                .delete(Path::new(name))
+                .expect("failed to delete file");
+        }
+
+        let mut file = writer
+            .open_write(Path::new("third.idx"))
+            .expect("failed to open file");
+
+        file.write_all(b"segment").expect("failed to write file");
+        file.terminate().expect("failed to close file");
+
+        writer.sync_directory().expect("failed to sync directory");
+        assert!(
+            writer
+                .exists(Path::new("third.idx"))
+                .expect("failed to check file")
+        );
+
+        writer
+            .atomic_write(Path::new("meta.json"), b"second")
+            .expect("failed to write meta.json");
+    });
+
+    write.await.expect("failed to write");
+
+    let version = directory.rollback(1).await.expect("failed to roll back");
+    assert_eq!(version, 3);
+
+    let reader = directory.clone();
+    let read = task::spawn_blocking(move || {
+        let meta = reader
+            .atomic_read(Path::new("meta.json"))
+            .expect("failed to read meta.json");
+
+        assert_eq!(meta, b"first");
+
+        let managed = reader
+            .atomic_read(Path::new(".managed.json"))
+            .expect("failed to read .managed.json");
+
+        assert_eq!(managed, br#"["first.idx","second.idx"]"#);
+
+        for name in ["first.idx", "second.idx"] {
+            let exists = reader
+                .exists(Path::new(name))
+                .expect("failed to check file");
+            assert!(exists, "{name} was not restored");
+        }
+
+        // Files committed since the revision are marked as deleted.
+        let exists = reader
+            .exists(Path::new("third.idx"))
+            .expect("failed to check file");
+        assert!(!exists, "third.idx was not deleted");
+
+        // The version written by the rollback is known.
+        reader
+            .atomic_write(Path::new("meta.json"), b"third")
+            .expect("failed to write meta.json");
+    });
+
+    read.await.expect("failed to read");
+
+    let deleted = sqlx::query_scalar!(
+        r#"
+        SELECT deleted
+        FROM tantivy.files
+        WHERE index = $1
+          AND path = 'third.idx'
+        "#,
+        id,
+    );
+
+    let deleted = deleted.fetch_one(&pool).await.expect("failed to read file");
+    assert!(deleted);
+
+    // Rolling back is refused once a referenced file has been removed, even if it is
+    // still registered.
+    let rollback = directory.rollback(2).await;
+    assert!(
+        rollback.is_ok(),
+        "failed to roll back to a revision without files"
+    );
+
+    operator
+        .delete(&format!("idx-{id}/first.idx"))
+        .await
+        .expect("failed to remove file");
+
+    let rollback = directory.rollback(1).await;
+    assert!(rollback.is_err());
+
+    let purge = sqlx::query!(
+        r#"
+        DELETE
+        FROM tantivy.files
+        WHERE index = $1
+          AND path = 'first.idx'
+        "#,
+        id,
+    );
+
+    purge.execute(&pool).await.expect("failed to purge file");
+
+    let rollback = directory.rollback(1).await;
+    assert!(rollback.is_err());
+
+    let version = directory.version().await.expect("failed to get version");
+    assert_eq!(version, Some(5));
+}
@@ -0,0 +1,123 @@
+use std::{
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicI64, Ordering},
+    },
+};
+
+use async_trait::async_trait;
+use eyre::{Result, bail};
+use sqlx::PgConnection;
+use tantivy::Directory;
+use tokio::task;
+use uuid::{Uuid, uuid};
+
+use super::{operator, pool};
+use crate::{CommitHook, Options, PostgresBackend, RemoteDirectory, WriteError};
+
+/// Stores the offset up to which messages have been indexed, in the same transaction as
+/// the commit, failing if it is negative.
+#[derive(Debug)]
+struct Offsets {
+    offset: Arc<AtomicI64>,
+}
+
+#[async_trait]
+impl CommitHook for Offsets {
+    async fn on_commit(&self, index: Uuid, _version: i64, conn: &mut PgConnection) -> Result<()> {
+        let offset = self.offset.load(Ordering::Acquire);
+        if offset < 0 {
+            bail!("invalid offset");
+        }
+
+        let query = sqlx::query(
+            r#"
+            INSERT INTO offsets
+              (index, "offset")
+            VALUES ($1, $2)
+            ON CONFLICT (index)
+            DO UPDATE SET "offset" = EXCLUDED."offset"
+            "#,
+        );
+
+        query.bind(index).bind(offset).execute(conn).await?;
+
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn commit_hook() {
+    let id = uuid!("4d6f8a0c-2e4a-4c6e-8b0d-2f4a6c8e0b1d");
+    let pool = pool(id).await;
+
+    let statements = [
+        r#"
+        CREATE TABLE IF NOT EXISTS offsets (
+          index UUID NOT NULL PRIMARY KEY,
+          "offset" BIGINT NOT NULL
+        )
+        "#,
+        "DELETE FROM offsets",
+    ];
+
+    for statement in statements {
+        let query = sqlx::query(statement);
+        query.execute(&pool).await.expect("failed to create table");
+    }
+
+    let offset = Arc::new(AtomicI64::new(42));
+    let hook = Offsets {
+        offset: Arc::clone(&offset),
+    };
+
+    let backend = PostgresBackend::new(pool.clone()).with_commit_hook(hook);
+    let directory = RemoteDirectory::open_with_backend(id, operator(), backend, Options::default())
+        .await
+        .expect("failed to open directory");
+
+    let offset_ = Arc::clone(&offset);
+    let write = task::spawn_blocking(move || {
+        let meta = Path::new("meta.json");
+        directory
+            .atomic_write(meta, b"first")
+            .expect("failed to write meta.json");
+
+        // The commit is rolled back along with the offset.
+        offset_.store(-1, Ordering::Release);
+        let error = directory
+            .atomic_write(meta, b"second")
+            .expect_err("write succeeded despite the hook failing");
+
+        let error = error
+            .get_ref()
+            .and_then(|error| error.downcast_ref::<WriteError>());
+
+        assert!(matches!(error, Some(WriteError::Other(_))));
+
+        let content = directory
+            .atomic_read(meta)
+            .expect("failed to read meta.json");
+
+        assert_eq!(content, b"first");
+    });
+
+    write.await.expect("failed to write");
+
+    let query = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT "offset"
+        FROM offsets
+        WHERE index = $1
+        "#,
+    );
+
+    let stored = query
+        .bind(id)
+        .fetch_one(&pool)
+        .await
+        .expect("failed to read offset");
+
+    assert_eq!(stored, 42);
+}
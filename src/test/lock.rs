@@ -0,0 +1,248 @@
+use std::{path::Path, thread, time::Duration};
+
+use tantivy::{
+    Directory,
+    directory::{INDEX_WRITER_LOCK, Lock, error::LockError},
+};
+use tokio::task;
+use uuid::uuid;
+
+use super::{operator, pool};
+use crate::{Locking, Options, RemoteDirectory, WriteError};
+
+#[tokio::test]
+async fn advisory() {
+    let id = uuid!("d3c8a7a2-52f1-4c1e-a0b5-0f3f2a9e6b47");
+    let pool = pool(id).await;
+
+    let options = Options {
+        locking: Locking::Advisory,
+        ..Default::default()
+    };
+
+    let mut directories = Vec::new();
+    for _ in 0..2 {
+        let directory =
+            RemoteDirectory::open_with_options(id, operator(), pool.clone(), options.clone())
+                .await
+                .expect("failed to open directory");
+
+        directories.push(directory);
+    }
+
+    let lock = task::spawn_blocking(move || {
+        let lock = directories[0]
+            .acquire_lock(&INDEX_WRITER_LOCK)
+            .expect("failed to acquire lock");
+
+        let busy = directories[1].acquire_lock(&INDEX_WRITER_LOCK);
+        assert!(matches!(busy, Err(LockError::LockBusy)));
+
+        // The lock is released as soon as it is dropped.
+        drop(lock);
+
+        let lock = directories[1]
+            .acquire_lock(&INDEX_WRITER_LOCK)
+            .expect("failed to acquire lock once released");
+
+        // Blocking locks are retried until the lock has been released.
+        let blocking = Lock {
+            filepath: INDEX_WRITER_LOCK.filepath.clone(),
+            is_blocking: true,
+        };
+
+        let busy = directories[0].acquire_lock(&INDEX_WRITER_LOCK);
+        assert!(matches!(busy, Err(LockError::LockBusy)));
+
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            drop(lock);
+        });
+
+        directories[0]
+            .acquire_lock(&blocking)
+            .expect("failed to acquire lock once released");
+
+        release.join().expect("failed to release lock");
+    });
+
+    lock.await.expect("failed to lock");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lease() {
+    let id = uuid!("5e0c1d3b-8f7a-4b2e-9d61-3a4c5b6d7e8f");
+    let pool = pool(id).await;
+
+    let options = Options {
+        locking: Locking::Lease {
+            ttl: Duration::from_nanos(2),
+        },
+        ..Default::default()
+    };
+
+    let open = RemoteDirectory::open_with_options(id, operator(), pool.clone(), options);
+    assert!(open.await.is_err(), "the TTL is too short");
+
+    let options = Options {
+        locking: Locking::Lease {
+            ttl: Duration::from_secs(60),
+        },
+        ..Default::default()
+    };
+
+    let mut directories = Vec::new();
+    for _ in 0..2 {
+        let directory =
+            RemoteDirectory::open_with_options(id, operator(), pool.clone(), options.clone())
+                .await
+                .expect("failed to open directory");
+
+        directories.push(directory);
+    }
+
+    let directories_ = directories.clone();
+    let lock = task::spawn_blocking(move || {
+        let lock = directories_[0]
+            .acquire_lock(&INDEX_WRITER_LOCK)
+            .expect("failed to acquire lock");
+
+        // The lease is deleted as soon as the lock is dropped.
+        drop(lock);
+
+        let lock = directories_[1]
+            .acquire_lock(&INDEX_WRITER_LOCK)
+            .expect("failed to acquire lock once released");
+
+        drop(lock);
+
+        let lock = directories_[0]
+            .acquire_lock(&INDEX_WRITER_LOCK)
+            .expect("failed to acquire lock");
+
+        let busy = directories_[1].acquire_lock(&INDEX_WRITER_LOCK);
+        assert!(matches!(busy, Err(LockError::LockBusy)));
+
+        lock
+    });
+
+    let lock = lock.await.expect("failed to lock");
+
+    // Simulates the holder of the lock hanging for longer than the lease.
+    let expire = sqlx::query!(
+        r#"
+        UPDATE tantivy.locks
+        SET last_alive_at = NOW() - INTERVAL '1 hour'
+        WHERE index = $1
+        "#,
+        id,
+    );
+
+    expire.execute(&pool).await.expect("failed to expire lease");
+
+    let takeover = task::spawn_blocking(move || {
+        directories[1]
+            .acquire_lock(&INDEX_WRITER_LOCK)
+            .expect("failed to take over lock")
+    });
+
+    let _takeover = takeover.await.expect("failed to take over lock");
+
+    // Releasing the lock which was taken over does not release the new one.
+    drop(lock);
+
+    let holders = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM tantivy.locks
+        WHERE index = $1
+        "#,
+        id,
+    );
+
+    let holders = holders
+        .fetch_one(&pool)
+        .await
+        .expect("failed to count holders");
+
+    assert_eq!(holders, 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fencing() {
+    let id = uuid!("9b2f4e61-7c3d-4a8b-b5e2-1d0c9f8a7b6e");
+    let pool = pool(id).await;
+
+    let options = Options {
+        locking: Locking::Lease {
+            ttl: Duration::from_secs(60),
+        },
+        ..Default::default()
+    };
+
+    let mut directories = Vec::new();
+    for _ in 0..2 {
+        let directory =
+            RemoteDirectory::open_with_options(id, operator(), pool.clone(), options.clone())
+                .await
+                .expect("failed to open directory");
+
+        directories.push(directory);
+    }
+
+    let directories_ = directories.clone();
+    let zombie = task::spawn_blocking(move || {
+        let lock = directories_[0]
+            .acquire_lock(&INDEX_WRITER_LOCK)
+            .expect("failed to acquire lock");
+
+        directories_[0]
+            .atomic_write(Path::new("meta.json"), b"zombie")
+            .expect("failed to write while holding the lock");
+
+        lock
+    });
+
+    let zombie = zombie.await.expect("failed to lock");
+
+    let expire = sqlx::query!(
+        r#"
+        UPDATE tantivy.locks
+        SET last_alive_at = NOW() - INTERVAL '1 hour'
+        WHERE index = $1
+        "#,
+        id,
+    );
+
+    expire.execute(&pool).await.expect("failed to expire lease");
+
+    let write = task::spawn_blocking(move || {
+        let _lock = directories[1]
+            .acquire_lock(&INDEX_WRITER_LOCK)
+            .expect("failed to take over lock");
+
+        directories[1]
+            .atomic_write(Path::new("meta.json"), b"new")
+            .expect("failed to write after taking over the lock");
+
+        // The writer which lost its lease can no longer roll back the new commit.
+        let error = directories[0]
+            .atomic_write(Path::new("meta.json"), b"zombie")
+            .expect_err("zombie writer was not fenced");
+
+        let error = error
+            .get_ref()
+            .and_then(|error| error.downcast_ref::<WriteError>());
+
+        assert!(matches!(error, Some(WriteError::Fenced { .. })));
+
+        directories[1]
+            .atomic_read(Path::new("meta.json"))
+            .expect("failed to read meta.json")
+    });
+
+    let content = write.await.expect("failed to write");
+    assert_eq!(content, b"new");
+
+    drop(zombie);
+}
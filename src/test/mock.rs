@@ -1 +1,119 @@
+use std::path::Path;
 
+use tantivy::Directory;
+use tokio::task;
+use uuid::uuid;
+
+use super::{index, operator};
+use crate::{
+    Expected, FileMetadata, HistoryOptions, Locking, MemoryBackend, MetadataBackend, Options,
+    RemoteDirectory, WriteError,
+};
+
+#[tokio::test]
+async fn memory() {
+    let id = uuid!("6e8a0c2e-4f6b-4d8a-9c1e-3a5c7e9b1d3f");
+    let backend = MemoryBackend::new().with_history(HistoryOptions {
+        retain: Some(1),
+        max_age: None,
+    });
+
+    let options = Options {
+        locking: Locking::Advisory,
+        ..Default::default()
+    };
+
+    let open = RemoteDirectory::open_with_backend(id, operator(), backend.clone(), options);
+    assert!(open.await.is_err(), "locking requires PostgreSQL");
+
+    let directory = index(id, operator(), backend.clone()).await;
+
+    // Only the current revision is retained, so the deleted segment could be removed.
+    let revisions = directory
+        .revisions()
+        .await
+        .expect("failed to list revisions");
+
+    assert_eq!(revisions.len(), 1);
+}
+
+#[tokio::test]
+async fn fencing() {
+    let id = uuid!("5d7f9b1d-3f5b-4d7f-9b1d-3f5b7d9f1b3d");
+    let backend = MemoryBackend::new();
+    backend.open(id).await.expect("failed to open index");
+
+    let epoch = backend.fence(id).await.expect("failed to fence");
+    assert_eq!(epoch, 1);
+
+    // Writes made with an older epoch are rejected, and leave the file untouched.
+    let write = backend.write(id, "meta.json", b"stale", Some(0), Expected::Any);
+    assert!(matches!(write.await, Err(WriteError::Fenced { epoch: 0 })));
+
+    let version = backend.version(id, "meta.json").await;
+    assert_eq!(version.expect("failed to get version"), None);
+
+    // Files committed along with a rejected write are not registered.
+    let file = FileMetadata {
+        length: 5,
+        etag: None,
+    };
+
+    let files = [(String::from("first.idx"), file)];
+    let commit = backend.commit(id, &files, "meta.json", b"stale", Some(0), Expected::Any);
+    assert!(matches!(commit.await, Err(WriteError::Fenced { epoch: 0 })));
+
+    let file = backend.file(id, "first.idx").await;
+    assert!(file.expect("failed to get file").is_none());
+
+    let write = backend.write(id, "meta.json", b"current", Some(1), Expected::Any);
+    assert_eq!(write.await.expect("failed to write meta.json"), 1);
+
+    // The rollback is fenced the same way.
+    let rollback = backend.rollback(id, 1, &[], &[], Some(0)).await;
+    assert!(rollback.is_err());
+
+    let version = backend.version(id, "meta.json").await;
+    assert_eq!(version.expect("failed to get version"), Some(1));
+}
+
+#[tokio::test]
+async fn rollback() {
+    let id = uuid!("1f3b5d7f-9a2c-4e6b-8d0f-2b4d6f8a0c2e");
+    let directory = RemoteDirectory::open_with_backend(
+        id,
+        operator(),
+        MemoryBackend::new(),
+        Options::default(),
+    )
+    .await
+    .expect("failed to open directory");
+
+    let writer = directory.clone();
+    let write = task::spawn_blocking(move || {
+        for content in [&b"first"[..], b"second"] {
+            writer
+                .atomic_write(Path::new(".managed.json"), b"[]")
+                .expect("failed to write .managed.json");
+
+            writer
+                .atomic_write(Path::new("meta.json"), content)
+                .expect("failed to write meta.json");
+        }
+    });
+
+    write.await.expect("failed to write");
+
+    let version = directory.rollback(1).await.expect("failed to roll back");
+    assert_eq!(version, 3);
+
+    let reader = directory.clone();
+    let read = task::spawn_blocking(move || {
+        reader
+            .atomic_read(Path::new("meta.json"))
+            .expect("failed to read meta.json")
+    });
+
+    let meta = read.await.expect("failed to read");
+    assert_eq!(meta, b"first");
+}
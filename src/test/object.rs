@@ -0,0 +1,161 @@
+use opendal::{
+    Operator,
+    services::{Fs, Memory},
+};
+use tokio::task::JoinSet;
+use uuid::uuid;
+
+use super::{conditional::ConditionalLayer, index};
+use crate::{Expected, FileMetadata, MetadataBackend, ObjectStorageBackend, WriteError};
+
+#[tokio::test]
+async fn memory() {
+    let id = uuid!("9d1f3b5d-7f9a-4c2e-8b4d-6f8a0c2e4a6b");
+    let operator = Operator::new(Memory::default())
+        .expect("failed to create operator")
+        .finish();
+
+    let backend = ObjectStorageBackend::new(operator.clone());
+    assert!(backend.is_err(), "conditional writes are not supported");
+
+    let operator = operator.layer(ConditionalLayer::default());
+    let backend = ObjectStorageBackend::new(operator.clone()).expect("failed to create backend");
+    index(id, operator, backend).await;
+}
+
+#[tokio::test]
+async fn fs() {
+    let id = uuid!("5b7d9f1b-3d5f-4a7c-9e1a-3c5e7a9c1e3a");
+    let root = std::env::temp_dir().join(format!("tantivy-remote-{id}"));
+    let root = root.to_str().expect("temporary directory is valid UTF-8");
+
+    let operator = Operator::new(Fs::default().root(root))
+        .expect("failed to create operator")
+        .finish();
+
+    let backend = ObjectStorageBackend::new(operator.clone());
+    assert!(
+        backend.is_err(),
+        "conditional writes are not fully supported"
+    );
+
+    let operator = operator.layer(ConditionalLayer::default());
+    operator.remove_all("/").await.expect("failed to clean up");
+
+    let backend = ObjectStorageBackend::new(operator.clone()).expect("failed to create backend");
+    index(id, operator, backend).await;
+}
+
+#[tokio::test]
+async fn concurrent() {
+    let id = uuid!("2c4e6a8c-0e2a-4c6e-8a0c-2e4a6c8e0a2c");
+    let operator = Operator::new(Memory::default())
+        .expect("failed to create operator")
+        .finish()
+        .layer(ConditionalLayer::default());
+
+    let backend = ObjectStorageBackend::new(operator.clone()).expect("failed to create backend");
+
+    // Concurrent writes are all applied, one after the other.
+    let mut writes = JoinSet::new();
+    for task in 0..8 {
+        let backend = backend.clone();
+        writes.spawn(async move {
+            for write in 0..8 {
+                let path = format!("{task}-{write}");
+                let file = FileMetadata {
+                    length: 1,
+                    etag: None,
+                };
+
+                backend
+                    .register(id, &[(path, file)])
+                    .await
+                    .expect("failed to register file");
+
+                let content = format!("{task}-{write}");
+                backend
+                    .write(id, "meta.json", content.as_bytes(), None, Expected::Any)
+                    .await
+                    .expect("failed to write meta.json");
+            }
+        });
+    }
+
+    writes.join_all().await;
+
+    let files = backend.files(id).await.expect("failed to list files");
+    assert_eq!(files.len(), 64);
+
+    let version = backend.version(id, "meta.json").await;
+    assert_eq!(version.expect("failed to read version"), Some(64));
+
+    // Only one of concurrent writes expecting the same version succeeds.
+    let mut writes = JoinSet::new();
+    for _ in 0..8 {
+        let backend = backend.clone();
+        writes.spawn(async move {
+            let expected = Expected::Version(64);
+            backend.write(id, "meta.json", b"{}", None, expected).await
+        });
+    }
+
+    let writes = writes.join_all().await;
+    let written = writes.iter().filter(|write| write.is_ok()).count();
+    assert_eq!(written, 1);
+
+    let conflicts = writes
+        .iter()
+        .filter(|write| matches!(write, Err(WriteError::Conflict { expected: Some(64) })))
+        .count();
+
+    assert_eq!(conflicts, 7);
+
+    // Files can only be created once.
+    let write = backend.write(id, "meta.json", b"{}", None, Expected::Missing);
+    assert!(matches!(
+        write.await,
+        Err(WriteError::Conflict { expected: None })
+    ));
+}
+
+#[tokio::test]
+async fn version_header() {
+    let id = uuid!("6a8c0e2a-4c6e-4a8c-8e2a-4c6e8a0c2e4a");
+    let operator = Operator::new(Memory::default())
+        .expect("failed to create operator")
+        .finish()
+        .layer(ConditionalLayer::default());
+
+    let backend = ObjectStorageBackend::new(operator.clone()).expect("failed to create backend");
+    for content in [&b"first"[..], b"second"] {
+        backend
+            .write(id, "meta.json", content, None, Expected::Any)
+            .await
+            .expect("failed to write meta.json");
+    }
+
+    // The version is stored at the start of the object, as a big-endian integer.
+    let object = operator
+        .read(&format!("meta-{id}/meta.json"))
+        .await
+        .expect("failed to read object")
+        .to_vec();
+
+    let (header, content) = object.split_at(size_of::<i64>());
+    assert_eq!(header, 2_i64.to_be_bytes());
+    assert_eq!(content, b"second");
+
+    let read = backend.read(id, "meta.json").await;
+    let read = read.expect("failed to read meta.json");
+    assert_eq!(read, Some((b"second".to_vec(), 2)));
+
+    // Objects too short to contain a version are rejected.
+    operator
+        .write(&format!("meta-{id}/meta.json"), b"short".to_vec())
+        .await
+        .expect("failed to write object");
+
+    let read = backend.read(id, "meta.json").await;
+    assert!(read.is_err(), "truncated object was read");
+}
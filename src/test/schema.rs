@@ -0,0 +1,350 @@
+use std::{path::Path, time::Duration};
+
+use sqlx::PgPool;
+use tantivy::{
+    Directory, Index, IndexSettings, ReloadPolicy,
+    collector::Count,
+    directory::WatchCallback,
+    doc,
+    query::AllQuery,
+    schema::{SchemaBuilder, TEXT},
+};
+use tokio::{sync::mpsc, task, time};
+use uuid::{Uuid, uuid};
+
+use super::{operator, pool};
+use crate::{
+    Expected, FileMetadata, HistoryOptions, Locking, MetadataBackend, Options, PostgresBackend,
+    RemoteDirectory, Watching,
+};
+
+/// The foreign keys created by the migrations, as the table and the table it
+/// references, along with the columns referencing it.
+const FOREIGN_KEYS: [(&str, &str, &str); 5] = [
+    ("metadata", "directories", "index"),
+    ("files", "directories", "index"),
+    ("locks", "directories", "index"),
+    ("revisions", "directories", "index"),
+    ("stats", "revisions", "index, version"),
+];
+
+/// The tables created by the migrations, which are copied to the custom schema.
+const TABLES: [&str; 6] = [
+    "directories",
+    "metadata",
+    "files",
+    "locks",
+    "revisions",
+    "stats",
+];
+
+#[tokio::test(flavor = "multi_thread")]
+async fn schema() {
+    let id = uuid!("7b9d1f3a-5c7e-4a9b-8d2f-4e6a8c0b2d4f");
+    let pool = pool(id).await;
+    tables(&pool).await;
+
+    let backend = PostgresBackend::new(pool.clone())
+        .with_schema("products")
+        .with_table_prefix("search_");
+
+    let options = Options {
+        locking: Locking::Lease {
+            ttl: Duration::from_secs(60),
+        },
+        watching: Watching::Poll {
+            interval: Duration::from_millis(50),
+        },
+        ..Default::default()
+    };
+
+    let directory = RemoteDirectory::open_with_backend(id, operator(), backend, options)
+        .await
+        .expect("failed to open directory");
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let watcher = directory.clone();
+    let handle = task::spawn_blocking(move || {
+        let callback = WatchCallback::new(move || {
+            let _ = tx.send(());
+        });
+
+        watcher.watch(callback).expect("failed to watch")
+    });
+
+    let _handle = handle.await.expect("failed to watch");
+
+    // Leaves time for the poller to record the initial version.
+    time::sleep(Duration::from_millis(200)).await;
+
+    let mut schema = SchemaBuilder::new();
+    let title = schema.add_text_field("title", TEXT);
+    let schema = schema.build();
+
+    let directory_ = directory.clone();
+    let write = task::spawn_blocking(move || {
+        let index = Index::create(directory_, schema, IndexSettings::default())
+            .expect("failed to create index");
+
+        let mut writer = index
+            .writer(15_000_000)
+            .expect("failed to create index writer");
+
+        writer
+            .add_document(doc!(title => "The Old Man and the Sea"))
+            .expect("failed to add document");
+
+        writer.commit().expect("failed to commit");
+
+        let reader = index
+            .reader_builder()
+            .reload_policy(ReloadPolicy::Manual)
+            .try_into()
+            .expect("failed to create index reader");
+
+        let count = reader
+            .searcher()
+            .search(&AllQuery, &Count)
+            .expect("failed to search");
+
+        assert_eq!(count, 1);
+    });
+
+    write.await.expect("failed to write");
+
+    time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("callback was not called")
+        .expect("callback was dropped");
+
+    let revisions = directory
+        .revisions()
+        .await
+        .expect("failed to list revisions");
+
+    assert!(!revisions.is_empty());
+
+    // Nothing is stored in the default schema.
+    let query = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM tantivy.metadata
+        WHERE index = $1
+        "#,
+        id,
+    );
+
+    let count = query.fetch_one(&pool).await.expect("failed to count");
+    assert_eq!(count, 0);
+
+    let read = task::spawn_blocking(move || directory.exists(Path::new("meta.json")));
+    let exists = read.await.expect("failed to read");
+    assert!(exists.expect("failed to check meta.json"));
+
+    // The queries are not checked at compile time, so they are all run against both
+    // the default tables and the custom ones.
+    let id = uuid!("3f5b7d9f-1b3d-4f5b-9d1f-3b5d7f9b1d3f");
+    let pool = super::pool(id).await;
+
+    queries(PostgresBackend::new(pool.clone()), id).await;
+
+    let backend = PostgresBackend::new(pool)
+        .with_schema("products")
+        .with_table_prefix("search_");
+
+    queries(backend, id).await;
+}
+
+/// Runs every query of the backend, checking that they return what is expected.
+async fn queries(backend: PostgresBackend, id: Uuid) {
+    let backend = backend.with_history(HistoryOptions {
+        retain: Some(1),
+        max_age: Some(Duration::from_secs(3600)),
+    });
+
+    backend.open(id).await.expect("failed to open index");
+    let epoch = Some(backend.fence(id).await.expect("failed to fence"));
+
+    let exists = backend.exists(id, "meta.json").await;
+    assert!(!exists.expect("failed to check meta.json"));
+
+    backend
+        .write(id, ".managed.json", b"[]", epoch, Expected::Missing)
+        .await
+        .expect("failed to write .managed.json");
+
+    let files = [(
+        String::from("first.idx"),
+        FileMetadata {
+            length: 5,
+            etag: Some(String::from("\"first\"")),
+        },
+    )];
+
+    let meta =
+        br#"{"segments":[{"max_doc":3,"deletes":{"num_deleted_docs":1}}],"opstamp":1,"schema":[]}"#;
+    let commit = backend.commit(id, &files, "meta.json", meta, epoch, Expected::Missing);
+    assert_eq!(commit.await.expect("failed to commit"), 1);
+
+    let read = backend.read(id, "meta.json").await;
+    assert_eq!(
+        read.expect("failed to read meta.json"),
+        Some((meta.to_vec(), 1))
+    );
+
+    let version = backend.version(id, "meta.json").await;
+    assert_eq!(version.expect("failed to read version"), Some(1));
+
+    let file = backend.file(id, "first.idx").await;
+    let file = file
+        .expect("failed to get file")
+        .expect("file is registered");
+    assert!(!file.deleted);
+    assert_eq!(file.length, Some(5));
+    assert_eq!(file.etag.as_deref(), Some("\"first\""));
+
+    let files = [(
+        String::from("second.idx"),
+        FileMetadata {
+            length: 6,
+            etag: None,
+        },
+    )];
+
+    backend
+        .register(id, &files)
+        .await
+        .expect("failed to register file");
+
+    let paths = ["first.idx", "second.idx", "missing.idx"].map(String::from);
+    let registered = backend.registered(id, &paths).await;
+    assert_eq!(
+        registered.expect("failed to list registered files").len(),
+        2
+    );
+
+    let write = backend.write(id, "meta.json", meta, epoch, Expected::Version(1));
+    assert_eq!(write.await.expect("failed to write meta.json"), 2);
+
+    // Only the most recent revision is retained, along with its statistics.
+    let revisions = backend.revisions(id).await;
+    let revisions = revisions.expect("failed to list revisions");
+    assert_eq!(
+        revisions
+            .iter()
+            .map(|revision| revision.version)
+            .collect::<Vec<_>>(),
+        [2]
+    );
+
+    let stats = backend.stats(id).await.expect("failed to list statistics");
+    let [stats] = stats.as_slice() else {
+        panic!("only the statistics of the retained revision should be stored");
+    };
+
+    assert_eq!((stats.version, stats.docs, stats.deleted_docs), (2, 2, 1));
+
+    let revision = backend.read_revision(id, 1).await;
+    assert!(revision.expect("failed to read revision").is_none());
+
+    let revision = backend.read_revision(id, 2).await;
+    let revision = revision.expect("failed to read revision");
+    let revision = revision.expect("revision is retained");
+    assert_eq!(revision.content, meta);
+    assert_eq!(revision.managed.as_deref(), Some(&b"[]"[..]));
+
+    // Rolling back deletes the files which are not part of the revision.
+    let paths = [String::from("first.idx")];
+    let rollback = backend.rollback(id, 2, &paths, &[], epoch).await;
+    assert_eq!(rollback.expect("failed to roll back"), Some(3));
+
+    let file = backend.file(id, "second.idx").await;
+    let file = file
+        .expect("failed to get file")
+        .expect("file is registered");
+    assert!(file.deleted);
+
+    backend
+        .delete(id, "first.idx")
+        .await
+        .expect("failed to delete file");
+
+    let deleted = backend.deleted(id, Duration::ZERO, 10).await;
+    let deleted = deleted.expect("failed to list deleted files");
+    let lengths = deleted
+        .iter()
+        .map(|file| (file.path.as_str(), file.length))
+        .collect::<Vec<_>>();
+
+    assert_eq!(lengths, [("second.idx", Some(6)), ("first.idx", Some(5))]);
+
+    let paths = deleted
+        .into_iter()
+        .map(|file| file.path)
+        .collect::<Vec<_>>();
+    backend
+        .purge(id, &paths)
+        .await
+        .expect("failed to purge files");
+
+    let files = backend.files(id).await.expect("failed to list files");
+    assert!(files.is_empty());
+}
+
+/// Creates the tables in the `products` schema, prefixed with `search_`, dropping
+/// them first if they already exist.
+async fn tables(pool: &PgPool) {
+    let statements = [
+        "DROP SCHEMA IF EXISTS products CASCADE",
+        "CREATE SCHEMA products",
+    ];
+
+    for statement in statements {
+        let create = sqlx::query(statement);
+        create.execute(pool).await.expect("failed to create schema");
+    }
+
+    for table in TABLES.iter().chain(&["schema_version"]) {
+        let sql = format!(
+            r#"
+            CREATE TABLE products.search_{table}
+              (LIKE tantivy.{table} INCLUDING ALL)
+            "#
+        );
+
+        let create = sqlx::query(&sql);
+        create.execute(pool).await.expect("failed to create table");
+    }
+
+    // Foreign keys are not copied along with the tables, but the statistics rely on
+    // them to be removed along with their revisions.
+    for (table, referenced, columns) in FOREIGN_KEYS {
+        let sql = format!(
+            r#"
+            ALTER TABLE products.search_{table}
+            ADD FOREIGN KEY ({columns})
+            REFERENCES products.search_{referenced}({columns})
+            ON DELETE CASCADE
+            "#
+        );
+
+        let create = sqlx::query(&sql);
+        create
+            .execute(pool)
+            .await
+            .expect("failed to create foreign key");
+    }
+
+    let record = sqlx::query(
+        r#"
+        INSERT INTO products.search_schema_version
+        SELECT version
+        FROM tantivy.schema_version
+        "#,
+    );
+
+    record
+        .execute(pool)
+        .await
+        .expect("failed to record schema version");
+}
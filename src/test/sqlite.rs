@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use sqlx::sqlite::SqlitePoolOptions;
+use tokio::time;
+use uuid::uuid;
+
+use super::{index, operator};
+use crate::{DeletedFile, MetadataBackend, Options, RemoteDirectory, SqliteBackend, Watching};
+
+#[tokio::test]
+async fn sqlite() {
+    let id = uuid!("a3c5e7f9-1b3d-4f5a-8c7e-9f1b3d5a7c9e");
+    let backend = backend().await;
+
+    let options = Options {
+        watching: Watching::Poll {
+            interval: Duration::from_secs(1),
+        },
+        ..Default::default()
+    };
+
+    let open = RemoteDirectory::open_with_backend(id, operator(), backend.clone(), options);
+    assert!(open.await.is_err(), "watching requires PostgreSQL");
+
+    let directory = index(id, operator(), backend).await;
+
+    let revisions = directory.revisions().await;
+    assert!(revisions.is_err(), "revisions are not supported");
+}
+
+#[tokio::test]
+async fn deleted_at() {
+    let id = uuid!("c7e9a1c3-5e7a-4c9e-a1c3-5e7a9c1e3a5c");
+    let backend = backend().await;
+    backend.open(id).await.expect("failed to open index");
+
+    for path in ["first.idx", "second.idx"] {
+        backend.delete(id, path).await.expect("failed to delete");
+        time::sleep(Duration::from_millis(50)).await;
+    }
+
+    // Deletions are ordered and compared to the grace period with sub-second
+    // precision.
+    let deleted = backend.deleted(id, Duration::ZERO, 1).await;
+    assert_eq!(
+        paths(deleted.expect("failed to list deleted")),
+        ["first.idx"]
+    );
+
+    let grace = Duration::from_millis(200);
+    let deleted = backend.deleted(id, grace, 10).await;
+    assert!(deleted.expect("failed to list deleted").is_empty());
+
+    time::sleep(grace).await;
+
+    let deleted = backend.deleted(id, grace, 10).await;
+    assert_eq!(
+        paths(deleted.expect("failed to list deleted")),
+        ["first.idx", "second.idx"]
+    );
+}
+
+/// Returns the paths of the given files.
+fn paths(files: Vec<DeletedFile>) -> Vec<String> {
+    files.into_iter().map(|file| file.path).collect()
+}
+
+/// Creates a backend storing the metadata in an in-memory database.
+async fn backend() -> SqliteBackend {
+    // Each connection to an in-memory database has its own database.
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .expect("failed to connect to database");
+
+    SqliteBackend::new(pool)
+        .await
+        .expect("failed to create backend")
+}
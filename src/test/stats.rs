@@ -0,0 +1,115 @@
+use std::path::Path;
+
+use tantivy::{
+    Directory, Index, IndexSettings, Term, doc,
+    schema::{STRING, SchemaBuilder},
+};
+use tokio::task;
+use uuid::uuid;
+
+use super::{operator, pool};
+use crate::RemoteDirectory;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stats() {
+    let id = uuid!("1a3c5e7a-9b1d-4f3a-8c5e-7a9b1d3f5a7b");
+    let directory = RemoteDirectory::open(id, operator(), pool(id).await)
+        .await
+        .expect("failed to open directory");
+
+    let directory_ = directory.clone();
+    let write = task::spawn_blocking(move || {
+        let mut schema = SchemaBuilder::new();
+        let title = schema.add_text_field("title", STRING);
+        let schema = schema.build();
+
+        let index = Index::create(directory_, schema, IndexSettings::default())
+            .expect("failed to create index");
+
+        let mut writer = index
+            .writer_with_num_threads(1, 15_000_000)
+            .expect("failed to create index writer");
+
+        for name in ["first", "second", "third"] {
+            writer
+                .add_document(doc!(title => name))
+                .expect("failed to add document");
+        }
+
+        writer.commit().expect("failed to commit");
+
+        writer.delete_term(Term::from_field_text(title, "second"));
+
+        let mut commit = writer.prepare_commit().expect("failed to prepare commit");
+        commit.set_payload("offset=42");
+        commit.commit().expect("failed to commit");
+    });
+
+    write.await.expect("failed to write");
+
+    let stats = directory.stats().await.expect("failed to list statistics");
+    let [latest, previous, ..] = stats.as_slice() else {
+        panic!("statistics of both commits should be stored");
+    };
+
+    assert!(latest.version > previous.version);
+    assert!(latest.opstamp > previous.opstamp);
+    assert_eq!(latest.segments, 1);
+    assert_eq!(latest.docs, 2);
+    assert_eq!(latest.deleted_docs, 1);
+    assert_eq!(latest.payload.as_deref(), Some("offset=42"));
+
+    assert_eq!(previous.docs, 3);
+    assert_eq!(previous.deleted_docs, 0);
+    assert_eq!(previous.payload, None);
+    assert_eq!(latest.schema_hash, previous.schema_hash);
+}
+
+#[tokio::test]
+async fn schema_hash() {
+    let id = uuid!("4e6a8c0e-2a4c-4e6a-8c0e-2a4c6e8a0c2e");
+    let directory = RemoteDirectory::open(id, operator(), pool(id).await)
+        .await
+        .expect("failed to open directory");
+
+    // The schema is hashed the same way regardless of the order of its keys.
+    let metas = [
+        r#"{"segments":[],"opstamp":1,"schema":[{"name":"title","type":"text","options":{"stored":true,"fast":false}}]}"#,
+        r#"{"schema":[{"options":{"fast":false,"stored":true},"type":"text","name":"title"}],"opstamp":2,"segments":[]}"#,
+    ];
+
+    let writer = directory.clone();
+    let write = task::spawn_blocking(move || {
+        for meta in metas {
+            writer
+                .atomic_write(Path::new("meta.json"), meta.as_bytes())
+                .expect("failed to write meta.json");
+        }
+    });
+
+    write.await.expect("failed to write");
+
+    let stats = directory.stats().await.expect("failed to list statistics");
+    let [latest, previous] = stats.as_slice() else {
+        panic!("statistics of both commits should be stored");
+    };
+
+    assert_eq!(latest.opstamp, 2);
+    assert_eq!(latest.schema_hash, previous.schema_hash);
+
+    // Statistics are not stored for inconsistent segments, but the commit still lands.
+    let meta =
+        r#"{"segments":[{"max_doc":1,"deletes":{"num_deleted_docs":2}}],"opstamp":3,"schema":[]}"#;
+
+    let writer = directory.clone();
+    let write = task::spawn_blocking(move || {
+        writer
+            .atomic_write(Path::new("meta.json"), meta.as_bytes())
+            .expect("failed to write meta.json");
+    });
+
+    write.await.expect("failed to write");
+
+    let stats = directory.stats().await.expect("failed to list statistics");
+    assert_eq!(stats.len(), 2);
+}
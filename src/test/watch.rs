@@ -0,0 +1,114 @@
+use std::{path::Path, time::Duration};
+
+use tantivy::{Directory, directory::WatchCallback};
+use tokio::{sync::mpsc, task, time};
+use uuid::{Uuid, uuid};
+
+use super::{operator, pool};
+use crate::{Options, PostgresBackend, RemoteDirectory, Watching, watch::Poller};
+
+#[cfg(feature = "notify")]
+#[tokio::test(flavor = "multi_thread")]
+async fn notify() {
+    let id = uuid!("c4a1e7f2-3b5d-4e6a-9c8b-7d2f1e0a3b4c");
+    watch(id, Watching::Notify).await;
+}
+
+#[cfg(feature = "notify")]
+#[test]
+fn notify_runtimes() {
+    let ids = [
+        uuid!("7e3b9d1f-5a2c-4f8e-b6d4-1c9a7e5f3b2d"),
+        uuid!("1a5c9e3b-7d2f-4b6a-8e1c-5f9d3b7a2e6c"),
+    ];
+
+    runtimes(ids, Watching::Notify);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn poll() {
+    let id = uuid!("0f6e2d4c-8a1b-4c3d-9e7f-5a6b7c8d9e0f");
+    let interval = Duration::from_millis(40);
+
+    watch(id, Watching::Poll { interval }).await;
+
+    // The poller stops once the handle has been dropped.
+    let backend = PostgresBackend::new(pool(id).await);
+    time::sleep(interval * 5).await;
+    assert!(!Poller::is_running(&backend, interval));
+
+    let options = Options {
+        watching: Watching::Poll {
+            interval: Duration::ZERO,
+        },
+        ..Default::default()
+    };
+
+    let open = RemoteDirectory::open_with_options(id, operator(), pool(id).await, options);
+    assert!(open.await.is_err(), "the interval must not be zero");
+}
+
+#[test]
+fn poll_runtimes() {
+    let ids = [
+        uuid!("3d7f1b5a-9c2e-4a6d-8f3b-7e1c5a9d2f4b"),
+        uuid!("8b2e6a4c-1f5d-4c9e-a7b3-2d6f8e4a1c5b"),
+    ];
+
+    let interval = Duration::from_millis(50);
+    runtimes(ids, Watching::Poll { interval });
+}
+
+/// Checks that watching using the given method works from successive runtimes, which
+/// requires the tasks watching for changes to be started again once the runtime they
+/// were spawned on is shut down.
+fn runtimes(ids: [Uuid; 2], watching: Watching) {
+    for id in ids {
+        let rt = tokio::runtime::Runtime::new().expect("failed to create runtime");
+        rt.block_on(watch(id, watching.clone()));
+    }
+}
+
+/// Checks that a callback registered on a directory watching using the given method
+/// is called when another directory writes `meta.json`.
+async fn watch(id: Uuid, watching: Watching) {
+    let pool = pool(id).await;
+
+    let options = Options {
+        watching,
+        ..Default::default()
+    };
+
+    let writer = RemoteDirectory::open(id, operator(), pool.clone())
+        .await
+        .expect("failed to open directory");
+
+    let reader = RemoteDirectory::open_with_options(id, operator(), pool, options)
+        .await
+        .expect("failed to open directory");
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let handle = task::spawn_blocking(move || {
+        let callback = WatchCallback::new(move || {
+            let _ = tx.send(());
+        });
+
+        reader.watch(callback).expect("failed to watch")
+    });
+
+    // The version is recorded when subscribing, so writing right away is noticed.
+    let _handle = handle.await.expect("failed to watch");
+
+    let write = task::spawn_blocking(move || {
+        writer
+            .atomic_write(Path::new("meta.json"), b"{}")
+            .expect("failed to write meta.json");
+    });
+
+    write.await.expect("failed to write");
+
+    time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("callback was not called")
+        .expect("callback was dropped");
+}
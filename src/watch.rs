@@ -0,0 +1,167 @@
+#[cfg(feature = "notify")]
+mod notify;
+mod poll;
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use derive_more::Debug;
+use sqlx::PgPool;
+use tantivy::directory::{WatchCallback, WatchCallbackList, WatchHandle};
+use uuid::Uuid;
+
+use crate::utils::FastConcurrentMap;
+
+#[cfg(feature = "notify")]
+pub(crate) use self::notify::{CHANNEL, Listener};
+pub(crate) use self::poll::Poller;
+
+/// Configures how [`RemoteDirectory`][1] watches for changes to `meta.json`, which is
+/// required to use `ReloadPolicy::OnCommitWithDelay`.
+///
+/// [1]: crate::RemoteDirectory
+#[derive(Clone, Debug, Default)]
+pub enum Watching {
+    /// Changes are not watched for, and readers have to be reloaded manually.
+    #[default]
+    Disabled,
+
+    /// Changes are watched for using PostgreSQL's `LISTEN` and `NOTIFY`, with a
+    /// single connection per database listening for changes to all the indexes of the
+    /// process.
+    ///
+    /// The connection is opened by the first directory which starts watching, and its
+    /// task is spawned on the runtime of that directory. If that runtime is shut down,
+    /// the callbacks are called one last time, and the next directory which starts
+    /// watching opens a new connection.
+    #[cfg(feature = "notify")]
+    Notify,
+
+    /// Changes are watched for by periodically checking the version of `meta.json`,
+    /// which does not require a dedicated connection and thus works behind poolers
+    /// such as PgBouncer in transaction mode.
+    ///
+    /// A single task polls for changes to all the indexes of the process stored in the
+    /// same database and tables, for each `interval`. It is spawned on the runtime of
+    /// the first directory which starts watching, and stops once no callbacks are
+    /// registered anymore. If that runtime is shut down, the callbacks are called one
+    /// last time, and the next directory which starts watching spawns a new task.
+    ///
+    /// The `interval` must not be zero.
+    Poll {
+        /// How often to check for changes.
+        interval: Duration,
+    },
+}
+
+/// Identifies the database which a pool connects to, so that directories using
+/// different pools for the same database can share the tasks watching for changes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Database {
+    host: String,
+    port: u16,
+    socket: Option<PathBuf>,
+    username: String,
+    database: Option<String>,
+}
+
+/// Keeps track of the callbacks to call when the `meta.json` of indexes changes.
+#[derive(Debug, Default)]
+pub(crate) struct Watchers {
+    /// Contains, for each index, the callbacks which have been registered for it.
+    #[debug(skip)]
+    watched: FastConcurrentMap<Uuid, Watched>,
+}
+
+/// The callbacks registered for an index.
+#[derive(Default)]
+struct Watched {
+    callbacks: WatchCallbackList,
+
+    /// The handles returned for the callbacks, used to know whether the index is still
+    /// being watched.
+    handles: Vec<Weak<WatchCallback>>,
+}
+
+impl Database {
+    /// Returns the database which the given pool connects to.
+    pub fn of(pool: &PgPool) -> Self {
+        let options = pool.connect_options();
+        Self {
+            host: options.get_host().to_owned(),
+            port: options.get_port(),
+            socket: options.get_socket().cloned(),
+            username: options.get_username().to_owned(),
+            database: options.get_database().map(str::to_owned),
+        }
+    }
+}
+
+impl Watchers {
+    /// Registers a callback to call when the `meta.json` of the given index changes,
+    /// until the returned [`WatchHandle`] is dropped.
+    pub fn subscribe(&self, index: Uuid, callback: WatchCallback) -> WatchHandle {
+        let mut entry = self.watched.entry_sync(index).or_default();
+        let watched = entry.get_mut();
+
+        // `WatchHandle` does not allow knowing whether it has been dropped, so it is
+        // wrapped into a callback which we keep a weak reference to.
+        let handle = watched.callbacks.subscribe(callback);
+        let handle = Arc::new(WatchCallback::new(move || {
+            let _handle = &handle;
+        }));
+
+        watched.handles.push(Arc::downgrade(&handle));
+
+        WatchHandle::new(handle)
+    }
+
+    /// Returns the indexes which are being watched, forgetting about those which are
+    /// not anymore.
+    pub fn indexes(&self) -> Vec<Uuid> {
+        let mut indexes = Vec::new();
+        self.watched.retain_sync(|index, watched| {
+            watched.handles.retain(|handle| handle.strong_count() > 0);
+            if watched.handles.is_empty() {
+                return false;
+            }
+
+            indexes.push(*index);
+            true
+        });
+
+        indexes
+    }
+
+    /// Returns whether the given index is still being watched.
+    pub fn is_watched(&self, index: &Uuid) -> bool {
+        let watched = self.watched.read_sync(index, |_, watched| {
+            watched
+                .handles
+                .iter()
+                .any(|handle| handle.strong_count() > 0)
+        });
+
+        watched.unwrap_or(false)
+    }
+
+    /// Calls the callbacks registered for the given index.
+    ///
+    /// The callbacks are called on a separate thread, which is not waited for.
+    pub fn broadcast(&self, index: Uuid) {
+        self.watched.read_sync(&index, |_, watched| {
+            drop(watched.callbacks.broadcast());
+        });
+    }
+
+    /// Calls the callbacks registered for all the indexes.
+    pub fn broadcast_all(&self) {
+        self.watched.iter_sync(|_, watched| {
+            drop(watched.callbacks.broadcast());
+            true
+        });
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use sqlx::{PgPool, postgres::PgListener};
+use tantivy::directory::{WatchCallback, WatchHandle};
+use tokio::{runtime::Handle, sync::OnceCell, time};
+use uuid::Uuid;
+
+use super::{Database, Watchers};
+use crate::utils::FastConcurrentMap;
+
+/// The channel on which a notification is sent, with the ID of the index as its
+/// payload, every time the `meta.json` of an index is written.
+pub(crate) const CHANNEL: &str = "tantivy_meta";
+
+/// How long to wait before receiving notifications again after failing to do so.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The listeners shared by all the directories of the process, keyed by the database
+/// they listen to.
+static LISTENERS: LazyLock<FastConcurrentMap<Database, Arc<OnceCell<Listener>>>> =
+    LazyLock::new(FastConcurrentMap::default);
+
+/// Listens for notifications sent when the `meta.json` of indexes is written, and
+/// calls the callbacks registered for them.
+#[derive(Clone, Debug)]
+pub(crate) struct Listener {
+    watchers: Arc<Watchers>,
+}
+
+/// Removes a listener once its task exits, e.g. because the runtime it was spawned on
+/// has been shut down, so that it is started again by the next directory which starts
+/// watching.
+struct Exit {
+    database: Database,
+    listener: Arc<OnceCell<Listener>>,
+    watchers: Arc<Watchers>,
+}
+
+impl Listener {
+    /// Returns the listener shared by all the directories of the process using the
+    /// same database as `pool`, starting it using a connection with the same options
+    /// as `pool` and spawning its task on `rt` if it has not been started yet.
+    pub async fn get(pool: &PgPool, rt: &Handle) -> sqlx::Result<Self> {
+        let database = Database::of(pool);
+        let listener = LISTENERS
+            .entry_async(database.clone())
+            .await
+            .or_default()
+            .get()
+            .clone();
+
+        let start = || Self::start(database, Arc::clone(&listener), pool, rt);
+        listener.get_or_try_init(start).await.cloned()
+    }
+
+    /// Registers a callback to call when the `meta.json` of the given index changes,
+    /// until the returned [`WatchHandle`] is dropped.
+    pub fn subscribe(&self, index: Uuid, callback: WatchCallback) -> WatchHandle {
+        self.watchers.subscribe(index, callback)
+    }
+
+    /// Starts listening for notifications, spawning a task calling the callbacks.
+    async fn start(
+        database: Database,
+        cell: Arc<OnceCell<Self>>,
+        pool: &PgPool,
+        rt: &Handle,
+    ) -> sqlx::Result<Self> {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(CHANNEL).await?;
+
+        let watchers = Arc::<Watchers>::default();
+        let exit = Exit {
+            database,
+            listener: cell,
+            watchers: Arc::clone(&watchers),
+        };
+
+        rt.spawn(Self::listen(listener, Arc::clone(&watchers), exit));
+
+        Ok(Self { watchers })
+    }
+
+    /// Receives notifications until the runtime is shut down, at which point `exit` is
+    /// dropped.
+    async fn listen(mut listener: PgListener, watchers: Arc<Watchers>, exit: Exit) {
+        let _exit = exit;
+        loop {
+            match listener.try_recv().await {
+                Ok(Some(notification)) => {
+                    if let Ok(index) = notification.payload().parse() {
+                        watchers.broadcast(index);
+                    }
+                }
+
+                // The connection has been lost and will be re-established on the next
+                // call, but notifications might have been missed in the meantime.
+                Ok(None) => watchers.broadcast_all(),
+                Err(_) => time::sleep(RETRY_DELAY).await,
+            }
+        }
+    }
+}
+
+impl Drop for Exit {
+    fn drop(&mut self) {
+        LISTENERS.remove_if_sync(&self.database, |listener| {
+            Arc::ptr_eq(listener, &self.listener)
+        });
+
+        // Changes are not notified anymore, which the callbacks are told about the same
+        // way as when the connection is lost: readers reload and find out by themselves.
+        self.watchers.broadcast_all();
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use scc::hash_map::Entry;
+use sqlx::PgPool;
+use tantivy::directory::{WatchCallback, WatchHandle};
+use tokio::{runtime::Handle, time};
+use uuid::Uuid;
+
+use super::{Database, Watchers};
+use crate::{PostgresBackend, utils::FastConcurrentMap};
+
+/// The pollers shared by all the directories of the process, keyed by the database and
+/// the table storing the metadata they poll, and by how often they poll it.
+static POLLERS: LazyLock<FastConcurrentMap<Key, Poller>> =
+    LazyLock::new(FastConcurrentMap::default);
+
+/// The key of a poller in [`POLLERS`].
+type Key = (Database, String, Duration);
+
+/// Periodically checks the version of the `meta.json` of the indexes being watched,
+/// and calls the callbacks registered for those which changed.
+#[derive(Clone, Debug)]
+pub(crate) struct Poller {
+    watchers: Arc<Watchers>,
+
+    /// Contains, for each index being watched, the last version of its `meta.json`
+    /// which was seen.
+    versions: Arc<FastConcurrentMap<Uuid, i64>>,
+}
+
+/// Removes a poller once its task exits, e.g. because the runtime it was spawned on
+/// has been shut down, so that it is started again by the next directory which starts
+/// watching.
+struct Exit {
+    key: Key,
+    watchers: Arc<Watchers>,
+}
+
+impl Poller {
+    /// Registers a callback to call when the `meta.json` of the given index changes,
+    /// until the returned [`WatchHandle`] is dropped.
+    ///
+    /// The callback is registered on the poller shared by all the directories of the
+    /// process using the same database and tables as `backend` and the same
+    /// `interval`, whose task is spawned on `rt` if it is not running.
+    pub async fn subscribe(
+        backend: &PostgresBackend,
+        interval: Duration,
+        rt: &Handle,
+        index: Uuid,
+        callback: WatchCallback,
+    ) -> sqlx::Result<WatchHandle> {
+        let metadata = &backend.tables().metadata;
+        let sql = format!(
+            r#"
+            SELECT index, version
+            FROM {metadata}
+            WHERE index = ANY($1)
+              AND path = 'meta.json'
+            "#
+        );
+
+        // The current version is read before subscribing, so that commits made before
+        // the first check of the poller are not missed.
+        let current = Self::versions(backend.pool(), &sql, &[index]).await?;
+        let version = current.get(&index).copied().unwrap_or(0);
+
+        // The callback is registered while holding the entry, so that the poller
+        // cannot stop in the meantime for lack of callbacks.
+        let key = (Database::of(backend.pool()), metadata.clone(), interval);
+        let (poller, handle, spawn) = match POLLERS.entry_sync(key.clone()) {
+            Entry::Occupied(entry) => {
+                let poller = entry.get().clone();
+                let handle = poller.watchers.subscribe(index, callback);
+                (poller, handle, false)
+            }
+
+            Entry::Vacant(entry) => {
+                let poller = Self {
+                    watchers: Arc::default(),
+                    versions: Arc::default(),
+                };
+
+                let handle = poller.watchers.subscribe(index, callback);
+                entry.insert_entry(poller.clone());
+                (poller, handle, true)
+            }
+        };
+
+        poller.versions.entry_sync(index).or_insert(version);
+
+        // The task is spawned once the entry has been released, as `exit` removes it
+        // when dropped.
+        if spawn {
+            let exit = Exit {
+                key: key.clone(),
+                watchers: Arc::clone(&poller.watchers),
+            };
+
+            let pool = backend.pool().clone();
+            rt.spawn(poller.poll(pool, sql, key, exit));
+        }
+
+        Ok(handle)
+    }
+
+    /// Returns whether the task of a poller using the same database and tables as
+    /// `backend` and the same `interval` is running.
+    #[cfg(test)]
+    pub fn is_running(backend: &PostgresBackend, interval: Duration) -> bool {
+        let metadata = backend.tables().metadata.clone();
+        let key = (Database::of(backend.pool()), metadata, interval);
+        POLLERS.contains_sync(&key)
+    }
+
+    /// Checks for changes every `interval` until no callbacks are registered anymore,
+    /// or until the runtime is shut down, at which point `exit` is dropped.
+    async fn poll(self, pool: PgPool, sql: String, key: Key, exit: Exit) {
+        let _exit = exit;
+        let mut interval = time::interval(key.2);
+
+        loop {
+            interval.tick().await;
+
+            let indexes = self.watchers.indexes();
+            self.versions
+                .retain_async(|index, _| self.watchers.is_watched(index))
+                .await;
+
+            if indexes.is_empty() {
+                // Callbacks are registered while holding the entry of the poller, so
+                // none can be registered while it is being removed.
+                let stop = POLLERS
+                    .remove_if_async(&key, |poller| {
+                        Arc::ptr_eq(&poller.watchers, &self.watchers)
+                            && self.watchers.indexes().is_empty()
+                    })
+                    .await;
+
+                if stop.is_some() {
+                    return;
+                }
+
+                continue;
+            }
+
+            // Failures are retried on the next tick.
+            let Ok(current) = Self::versions(&pool, &sql, &indexes).await else {
+                continue;
+            };
+
+            for index in indexes {
+                // Indexes which have not been committed to yet have no `meta.json`.
+                let version = current.get(&index).copied().unwrap_or(0);
+
+                // Indexes whose version has not been recorded by `subscribe()` yet are
+                // skipped, as the commits made in the meantime would be missed.
+                let previous = self
+                    .versions
+                    .update_async(&index, |_, previous| std::mem::replace(previous, version))
+                    .await;
+
+                if previous.is_some_and(|previous| previous != version) {
+                    self.watchers.broadcast(index);
+                }
+            }
+        }
+    }
+
+    /// Returns the current version of the `meta.json` of the given indexes, using the
+    /// given query.
+    async fn versions(
+        pool: &PgPool,
+        sql: &str,
+        indexes: &[Uuid],
+    ) -> sqlx::Result<HashMap<Uuid, i64>> {
+        let query = sqlx::query_as::<_, (Uuid, i64)>(sql).bind(indexes);
+        let rows = query.fetch_all(pool).await?;
+
+        Ok(rows.into_iter().collect())
+    }
+}
+
+impl Drop for Exit {
+    fn drop(&mut self) {
+        POLLERS.remove_if_sync(&self.key, |poller| {
+            Arc::ptr_eq(&poller.watchers, &self.watchers)
+        });
+
+        // Changes are not polled for anymore, which the callbacks are told about so that
+        // readers reload and find out by themselves.
+        self.watchers.broadcast_all();
+    }
+}
@@ -1,7 +1,7 @@
 use std::{
     io::{self, Write},
     pin::Pin,
-    task::{Context, Poll},
+    task::{Context, Poll, ready},
 };
 
 use opendal::FuturesAsyncWriter;
@@ -21,6 +21,10 @@ pin_project! {
         #[pin]
         writer: Compat<FuturesAsyncWriter>,
         entry: CreatedEntry,
+
+        // The number of bytes written so far, which is the length of the file once
+        // it is closed.
+        length: u64,
     }
 }
 
@@ -29,13 +33,21 @@ impl Writer {
         let writer = writer.into_futures_async_write();
         let writer = writer.compat_write();
 
-        Self { rt, writer, entry }
+        Self {
+            rt,
+            writer,
+            entry,
+            length: 0,
+        }
     }
 }
 
 impl Write for Writer {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.rt.block_on(async { self.writer.write(buf).await })
+        let written = self.rt.block_on(async { self.writer.write(buf).await })?;
+        self.length += written as u64;
+
+        Ok(written)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -46,7 +58,10 @@ impl Write for Writer {
 impl AsyncWrite for Writer {
     fn poll_write(self: Pin<&mut Self>, ctx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
         let this = self.project();
-        this.writer.poll_write(ctx, buf)
+        let written = ready!(this.writer.poll_write(ctx, buf))?;
+        *this.length += written as u64;
+
+        Poll::Ready(Ok(written))
     }
 
     fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<io::Result<()>> {
@@ -59,7 +74,7 @@ impl AsyncWrite for Writer {
         match this.writer.poll_shutdown(ctx)? {
             Poll::Pending => Poll::Pending,
             Poll::Ready(()) => {
-                this.entry.done();
+                this.entry.done(*this.length);
                 Poll::Ready(Ok(()))
             }
         }
@@ -69,6 +84,9 @@ impl AsyncWrite for Writer {
 impl TerminatingWrite for Writer {
     fn terminate_ref(&mut self, _: AntiCallToken) -> io::Result<()> {
         // TODO(MLB): flush as well?
-        self.rt.block_on(async { self.writer.shutdown().await })
+        self.rt.block_on(async { self.writer.shutdown().await })?;
+        self.entry.done(self.length);
+
+        Ok(())
     }
 }